@@ -0,0 +1,84 @@
+use crate::*;
+use alloc::string::String;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// The input is probably wrong but can be handled.
+    Warning,
+    /// The input is wrong and the output doesn't match what the writer meant.
+    Error,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Code {
+    /// The tag is opened with "{" but never closed with "}".
+    UnclosedTag,
+    /// The tag name is not a known function.
+    UnknownFunc,
+    /// The coordinates in {exit} are missing or not valid numbers.
+    BadExitCoords,
+    /// The expression contains an operator that doesn't exist.
+    InvalidOp,
+    /// The closing tag doesn't have a matching opening tag.
+    UnmatchedClose,
+    /// The transition effect in {exit} is not supported by Bitsy.
+    UnknownTransition,
+}
+
+impl Code {
+    /// Short unique identifier of the code, like "E001".
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Code::UnclosedTag => "E001",
+            Code::UnknownFunc => "W002",
+            Code::BadExitCoords => "E003",
+            Code::InvalidOp => "E004",
+            Code::UnmatchedClose => "W005",
+            Code::UnknownTransition => "W006",
+        }
+    }
+
+    pub fn level(&self) -> Level {
+        match self {
+            Code::UnclosedTag => Level::Error,
+            Code::UnknownFunc => Level::Warning,
+            Code::BadExitCoords => Level::Error,
+            Code::InvalidOp => Level::Error,
+            Code::UnmatchedClose => Level::Warning,
+            Code::UnknownTransition => Level::Warning,
+        }
+    }
+}
+
+/// A problem found in the source text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub code: Code,
+    /// Human-readable description of the problem.
+    pub message: String,
+    /// Position of the problematic part of the source text.
+    pub span: Span,
+}
+
+impl Diagnostic {
+    /// Create a diagnostic for the given byte range.
+    ///
+    /// Lines and columns are calculated later, when the whole text is known.
+    pub(crate) fn new(code: Code, message: String, start: usize, end: usize) -> Self {
+        let start = Pos {
+            offset: start,
+            ..Pos::default()
+        };
+        let end = Pos {
+            offset: end,
+            ..Pos::default()
+        };
+        Self {
+            level: code.level(),
+            code,
+            message,
+            span: Span { start, end },
+        }
+    }
+}
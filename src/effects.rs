@@ -0,0 +1,89 @@
+use crate::*;
+use libm::{cosf, fabsf, roundf, sinf};
+
+/// Vertical offset of a wavy character in font pixels.
+///
+/// `time` is in milliseconds, `col` is the character index in the line.
+pub fn wavy(time: u32, col: usize) -> f32 {
+    sinf(time as f32 / 250. - col as f32 / 2.) * 4.
+}
+
+/// Offset of a shaky character in font pixels.
+///
+/// `col` is the character index in the line, `row` is the line index on the page.
+/// The formulas are the same as in the "disturb" function of Bitsy.
+pub fn shaky(time: u32, col: usize, row: usize) -> (f32, f32) {
+    let (t, col, row) = (time as f32, col as f32, row as f32);
+    let dx = 3. * cosf(t * 0.1 - row) * sinf(t * 3. - col * 0.7) * cosf(t * 0.2 - col * 0.3);
+    let dy = 3. * sinf(t * 0.1 - col * 0.5) * cosf(t * 0.3 - col * 0.2) * sinf(t * 2. - row);
+    (dx, dy)
+}
+
+/// RGB color of a rainbow character.
+pub fn rainbow(time: u32, col: usize) -> [u8; 3] {
+    let h = fabsf(sinf(time as f32 / 600. - col as f32 / 8.));
+    hsl_to_rgb(h, 1., 0.5)
+}
+
+/// Convert HSL color with all components in 0..=1 range into RGB.
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> [u8; 3] {
+    if s == 0. {
+        let v = to_byte(l);
+        return [v, v, v];
+    }
+    let q = if l < 0.5 { l * (1. + s) } else { l + s - l * s };
+    let p = 2. * l - q;
+    let r = hue_to_rgb(p, q, h + 1. / 3.);
+    let g = hue_to_rgb(p, q, h);
+    let b = hue_to_rgb(p, q, h - 1. / 3.);
+    [to_byte(r), to_byte(g), to_byte(b)]
+}
+
+/// Convert a color component from 0..=1 range into 0..=255, rounding like Bitsy.
+fn to_byte(v: f32) -> u8 {
+    roundf(v * 255.) as u8
+}
+
+fn hue_to_rgb(p: f32, q: f32, mut t: f32) -> f32 {
+    if t < 0. {
+        t += 1.;
+    }
+    if t > 1. {
+        t -= 1.;
+    }
+    if t < 1. / 6. {
+        return p + (q - p) * 6. * t;
+    }
+    if t < 1. / 2. {
+        return q;
+    }
+    if t < 2. / 3. {
+        return p + (q - p) * (2. / 3. - t) * 6.;
+    }
+    p
+}
+
+impl Effects {
+    /// Offset of the character in font pixels caused by wavy and shaky effects.
+    pub fn offset(&self, time: u32, col: usize, row: usize) -> (f32, f32) {
+        let (mut dx, mut dy) = (0., 0.);
+        if self.wavy {
+            dy += wavy(time, col);
+        }
+        if self.shaky {
+            let (sx, sy) = shaky(time, col, row);
+            dx += sx;
+            dy += sy;
+        }
+        (dx, dy)
+    }
+
+    /// The color of the character if it's changed by the rainbow effect.
+    pub fn rainbow(&self, time: u32, col: usize) -> Option<[u8; 3]> {
+        if self.rainbow {
+            Some(rainbow(time, col))
+        } else {
+            None
+        }
+    }
+}
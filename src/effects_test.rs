@@ -0,0 +1,67 @@
+use crate::*;
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 0.001
+}
+
+#[test]
+fn test_effects_wavy() {
+    assert!(close(wavy(0, 0), 0.));
+    assert!(close(wavy(250, 0), 1f32.sin() * 4.));
+    assert!(close(wavy(0, 2), -1f32.sin() * 4.));
+    for t in (0..5000).step_by(37) {
+        assert!(wavy(t, 3).abs() <= 4.);
+    }
+}
+
+#[test]
+fn test_effects_shaky() {
+    assert_eq!(shaky(0, 0, 0), (0., 0.));
+    for t in (0..5000).step_by(37) {
+        let (dx, dy) = shaky(t, 5, 1);
+        assert!(dx.abs() <= 3.);
+        assert!(dy.abs() <= 3.);
+    }
+    // Values calculated using the formulas from the Bitsy source code.
+    let cases = [
+        ((1, 0, 0), (0.412848, 0.260171)),
+        ((5, 2, 1), (2.0834, -0.268865)),
+        ((16, 7, 1), (-0.867264, -1.108939)),
+    ];
+    for ((t, col, row), (x, y)) in cases {
+        let (dx, dy) = shaky(t, col, row);
+        assert!(close(dx, x), "x at {t},{col},{row}: {dx}");
+        assert!(close(dy, y), "y at {t},{col},{row}: {dy}");
+    }
+}
+
+#[test]
+fn test_effects_rainbow() {
+    // Hue 0 is red.
+    assert_eq!(rainbow(0, 0), [255, 0, 0]);
+    // sin(pi/2) = 1, hue 1 is red again.
+    let t = (core::f32::consts::FRAC_PI_2 * 600.) as u32;
+    assert_eq!(rainbow(t, 0), [255, 0, 0]);
+    // |sin(-1)| is about 0.84, it's magenta.
+    let [r, g, b] = rainbow(0, 8);
+    assert_eq!((r, g), (255, 0));
+    // 0.951 * 255 is 242.55, rounded up.
+    assert_eq!(b, 243);
+}
+
+#[test]
+fn test_effects_combined() {
+    let none = Effects::default();
+    assert_eq!(none.offset(1234, 3, 1), (0., 0.));
+    assert_eq!(none.rainbow(1234, 3), None);
+
+    let wavy_eff = none.with(TextEffect::Wavy);
+    assert_eq!(wavy_eff.offset(1234, 3, 1), (0., wavy(1234, 3)));
+
+    let both = wavy_eff.with(TextEffect::Shaky);
+    let (sx, sy) = shaky(1234, 3, 1);
+    assert_eq!(both.offset(1234, 3, 1), (sx, wavy(1234, 3) + sy));
+
+    let rbw = none.with(TextEffect::Rainbow);
+    assert_eq!(rbw.rainbow(600, 2), Some(rainbow(600, 2)));
+}
@@ -0,0 +1,149 @@
+use crate::*;
+use alloc::string::String;
+use alloc::string::ToString;
+use core::str::FromStr;
+use hashbrown::HashMap;
+
+/// The default Bitsy font data. Covers printable ASCII characters.
+const ASCII_SMALL: &str = include_str!("fonts/ascii_small.bitsyfont");
+
+/// A bitmap font in the Bitsy font format.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Font {
+    pub name: String,
+    /// The default glyph width in pixels.
+    pub width: u8,
+    /// The default glyph height in pixels.
+    pub height: u8,
+    pub chars: HashMap<char, FontChar>,
+}
+
+/// A single character of a font.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct FontChar {
+    pub width: u8,
+    pub height: u8,
+    /// Where to draw the glyph relative to the character cell.
+    pub offset_x: i8,
+    pub offset_y: i8,
+    /// How far to move the cursor after drawing the character.
+    pub spacing: u8,
+    /// Pixels, row by row, `true` is filled.
+    pub pixels: Frame,
+}
+
+impl Font {
+    /// Parse a font in the Bitsy font format.
+    ///
+    /// Invalid values are skipped or replaced by defaults.
+    pub fn parse(text: &str) -> Self {
+        let mut font = Font::default();
+        // The code point and the glyph of the character being parsed.
+        let mut current: Option<(char, FontChar)> = None;
+        for line in text.lines() {
+            let line = line.trim_ascii();
+            if line.is_empty() {
+                continue;
+            }
+            if line.chars().all(|ch| ch == '0' || ch == '1') {
+                if let Some((_, ch)) = &mut current {
+                    ch.pixels.push(line.chars().map(|ch| ch == '1').collect());
+                }
+                continue;
+            }
+            let (name, val) = line.split_once(' ').unwrap_or((line, ""));
+            let val = val.trim_ascii();
+            match name {
+                "FONT" => font.name = val.to_string(),
+                "CHAR" => {
+                    if let Some((code, ch)) = current.take() {
+                        font.chars.insert(code, ch);
+                    }
+                    let code = val.parse().ok().and_then(char::from_u32);
+                    current = code.map(|code| (code, font.new_char()));
+                }
+                "SIZE" => {
+                    let (w, h) = parse_pair(val);
+                    match &mut current {
+                        Some((_, ch)) => {
+                            (ch.width, ch.height) = (w, h);
+                            ch.spacing = w;
+                        }
+                        None => (font.width, font.height) = (w, h),
+                    }
+                }
+                "OFFSET" => {
+                    if let Some((_, ch)) = &mut current {
+                        (ch.offset_x, ch.offset_y) = parse_pair(val);
+                    }
+                }
+                "SPACING" => {
+                    if let Some((_, ch)) = &mut current {
+                        ch.spacing = val.parse().unwrap_or(ch.spacing);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some((code, ch)) = current {
+            font.chars.insert(code, ch);
+        }
+        font
+    }
+
+    /// The font used by Bitsy when the game doesn't specify one.
+    pub fn ascii_small() -> Self {
+        Self::parse(ASCII_SMALL)
+    }
+
+    /// Get the character glyph.
+    ///
+    /// Characters missing in the font are shown as "?".
+    pub fn get(&self, ch: char) -> Option<&FontChar> {
+        self.chars.get(&ch).or_else(|| self.chars.get(&'?'))
+    }
+
+    /// A character with the default font size.
+    fn new_char(&self) -> FontChar {
+        FontChar {
+            width: self.width,
+            height: self.height,
+            spacing: self.width,
+            ..FontChar::default()
+        }
+    }
+}
+
+impl FontData {
+    /// Parse the font definition.
+    pub fn font(&self) -> Font {
+        let mut font = Font::parse(&self.data);
+        if font.name.is_empty() {
+            font.name = self.name.clone();
+        }
+        font
+    }
+}
+
+impl CharWidth for Font {
+    fn char_width(&self, ch: char) -> u16 {
+        match self.get(ch) {
+            Some(ch) => u16::from(ch.spacing),
+            None => u16::from(self.width),
+        }
+    }
+}
+
+impl CharWidth for &Font {
+    fn char_width(&self, ch: char) -> u16 {
+        (*self).char_width(ch)
+    }
+}
+
+/// Parse "a b" pair of numbers.
+fn parse_pair<T: FromStr + Default>(val: &str) -> (T, T) {
+    let (a, b) = val.split_once(' ').unwrap_or((val, ""));
+    let a = a.trim_ascii().parse().unwrap_or_default();
+    let b = b.trim_ascii().parse().unwrap_or_default();
+    (a, b)
+}
@@ -0,0 +1,90 @@
+use crate::*;
+
+const FONT: &str = "FONT tiny
+
+SIZE 3 4
+
+CHAR 65
+010
+101
+111
+101
+
+CHAR 105
+SIZE 1 4
+OFFSET 1 -1
+SPACING 3
+1
+0
+1
+1
+
+CHAR 63
+111
+001
+010
+000
+";
+
+#[test]
+fn test_font_parse() {
+    let font = Font::parse(FONT);
+    assert_eq!(font.name, "tiny");
+    assert_eq!((font.width, font.height), (3, 4));
+    assert_eq!(font.chars.len(), 3);
+
+    let a = &font.chars[&'A'];
+    assert_eq!((a.width, a.height, a.spacing), (3, 4, 3));
+    assert_eq!((a.offset_x, a.offset_y), (0, 0));
+    assert_eq!(a.pixels[1], vec![true, false, true]);
+
+    let i = &font.chars[&'i'];
+    assert_eq!((i.width, i.height, i.spacing), (1, 4, 3));
+    assert_eq!((i.offset_x, i.offset_y), (1, -1));
+    assert_eq!(
+        i.pixels,
+        vec![vec![true], vec![false], vec![true], vec![true]]
+    );
+
+    assert_eq!(font.get('Z'), font.chars.get(&'?'));
+    assert_eq!(font.char_width('A'), 3);
+    assert_eq!(font.char_width('i'), 3);
+}
+
+#[test]
+fn test_font_ascii_small() {
+    let font = Font::ascii_small();
+    assert_eq!(font.name, "ascii_small");
+    assert_eq!((font.width, font.height), (6, 8));
+    for code in 32..127 {
+        let ch = char::from_u32(code).unwrap();
+        let glyph = &font.chars[&ch];
+        assert_eq!(glyph.pixels.len(), 8);
+        assert!(glyph.pixels.iter().all(|row| row.len() == 6));
+    }
+    assert!(font.chars[&' '].pixels.iter().flatten().all(|p| !p));
+    assert!(font.chars[&'A'].pixels.iter().flatten().any(|p| *p));
+}
+
+#[test]
+fn test_font_data() {
+    let data = FontData {
+        name: "tiny".to_string(),
+        data: FONT.to_string(),
+    };
+    assert_eq!(data.font(), Font::parse(FONT));
+    let data = FontData {
+        name: "noname".to_string(),
+        data: "SIZE 3 4".to_string(),
+    };
+    assert_eq!(data.font().name, "noname");
+}
+
+#[test]
+fn test_font_layout() {
+    let font = Font::ascii_small();
+    let mut state = State::default();
+    let words = Interpreter::new(Tokenizer::new("hello world"), &mut state);
+    let pages = Layout::new(&font).pages(words);
+    assert_eq!(pages[0].lines[0].width, 11 * 6);
+}
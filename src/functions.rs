@@ -0,0 +1,73 @@
+use crate::*;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+/// The result of calling a custom function.
+#[derive(Debug, PartialEq)]
+pub enum FuncResult {
+    /// The value used when the function is called inside of an expression.
+    ///
+    /// The value is not shown when the function is called as a tag.
+    Val(Val),
+    /// Words to show in place of the tag.
+    ///
+    /// The value is undefined when the function is called inside of an expression.
+    Words(Vec<Word>),
+}
+
+impl From<Val> for FuncResult {
+    fn from(val: Val) -> Self {
+        Self::Val(val)
+    }
+}
+
+impl From<Vec<Word>> for FuncResult {
+    fn from(words: Vec<Word>) -> Self {
+        Self::Words(words)
+    }
+}
+
+type Handler = Box<dyn Fn(&[Val], &mut State) -> FuncResult>;
+
+/// Custom functions that can be called from dialogs.
+///
+/// Functions are called both as tags, like `{myfunc 1 "a"}`,
+/// and inside of expressions, like `{x = {myfunc 1 "a"} + 1}`.
+///
+/// Built-in tags, like `item`, `give`, or `say`, always win:
+/// a function with the same name is never called.
+#[derive(Default)]
+pub struct Functions {
+    handlers: HashMap<String, Handler>,
+}
+
+impl Functions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the handler for the function with the given name.
+    ///
+    /// The handler receives evaluated arguments and the interpreter state.
+    /// If a function with the same name is already registered, it's replaced.
+    pub fn register<F>(&mut self, name: &str, handler: F)
+    where
+        F: Fn(&[Val], &mut State) -> FuncResult + 'static,
+    {
+        self.handlers.insert(name.to_string(), Box::new(handler));
+    }
+
+    /// Check if there is a function with the given name.
+    pub fn contains(&self, name: &str) -> bool {
+        self.handlers.contains_key(name)
+    }
+
+    /// Call the function. Returns `None` if it's not registered.
+    pub fn call(&self, name: &str, args: &[Val], state: &mut State) -> Option<FuncResult> {
+        let handler = self.handlers.get(name)?;
+        Some(handler(args, state))
+    }
+}
@@ -0,0 +1,249 @@
+use crate::*;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// How often animated drawings switch frames, in milliseconds.
+const FRAME_DURATION: u32 = 400;
+
+/// The ID of the sprite used as the player's avatar.
+const AVATAR: &str = "A";
+
+/// Direction of the avatar movement.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Dir {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Something that happened in the game as a result of the player input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The avatar moved to a new position.
+    Moved,
+    /// The avatar bumped into a wall, a sprite, or the room edge.
+    Blocked,
+    /// The avatar picked up the item.
+    Item(ID),
+    /// The avatar went into another room, with the given transition effect.
+    Exit(ID, Option<Transition>),
+    /// The dialog should be shown. Use [`Game::dialog`] to interpret it.
+    Dialog(ID),
+    /// The game is over. Contains the ID of the ending dialog.
+    End(ID),
+}
+
+/// Headless Bitsy game runtime.
+///
+/// Moves the avatar around and reports what happened.
+/// Rendering, reading input, and showing dialogs is up to the host.
+pub struct Game {
+    /// The game data. Items picked up by the player are removed from rooms.
+    pub world: World,
+    pub state: State,
+    /// Custom functions that can be called from dialogs.
+    funcs: Functions,
+    /// Time since the game start in milliseconds.
+    time: u32,
+}
+
+impl Game {
+    /// Start a new game. The avatar is placed where the game data says.
+    pub fn new(world: World) -> Self {
+        let mut state = State {
+            avatar: AVATAR.to_string(),
+            ..State::default()
+        };
+        if let Some(avatar) = world.sprite(AVATAR) {
+            if let Some((room, x, y)) = &avatar.pos {
+                state.room = room.clone();
+                state.pos_x = *x;
+                state.pos_y = *y;
+            }
+            for (item, count) in &avatar.inventory {
+                state.inventory.add(item.clone(), *count);
+            }
+        }
+        state.vars.load(&world.variables);
+        if let Some(room) = world.room(&state.room) {
+            state.palette = room.palette.clone().unwrap_or_default();
+        }
+        Self {
+            world,
+            state,
+            funcs: Functions::new(),
+            time: 0,
+        }
+    }
+
+    /// Use the given custom functions in dialogs.
+    pub fn with_functions(mut self, funcs: Functions) -> Self {
+        self.funcs = funcs;
+        self
+    }
+
+    /// Advance the game time by the given number of milliseconds.
+    pub fn tick(&mut self, dt: u32) {
+        self.time = self.time.wrapping_add(dt);
+    }
+
+    /// Select the current animation frame of a drawing.
+    pub fn frame<'a>(&self, frames: &'a [Frame]) -> Option<&'a Frame> {
+        if frames.is_empty() {
+            return None;
+        }
+        let index = (self.time / FRAME_DURATION) as usize % frames.len();
+        frames.get(index)
+    }
+
+    /// The room where the avatar currently is.
+    pub fn room(&self) -> Option<&Room> {
+        self.world.room(&self.state.room)
+    }
+
+    /// Find a sprite (except the avatar) at the given position in the current room.
+    pub fn sprite_at(&self, x: u8, y: u8) -> Option<&Sprite> {
+        self.world.sprites.iter().find(|s| {
+            let Some((room, sx, sy)) = &s.pos else {
+                return false;
+            };
+            s.id != AVATAR && *room == self.state.room && *sx == x && *sy == y
+        })
+    }
+
+    /// Check if the tile at the given position in the current room is a wall.
+    pub fn is_wall(&self, x: u8, y: u8) -> bool {
+        let Some(room) = self.room() else {
+            return false;
+        };
+        let Some(row) = room.tiles.get(usize::from(y)) else {
+            return true;
+        };
+        let Some(id) = row.get(usize::from(x)) else {
+            return true;
+        };
+        if room.walls.contains(id) {
+            return true;
+        }
+        match self.world.tile(id) {
+            Some(tile) => tile.wall == Some(true),
+            None => false,
+        }
+    }
+
+    /// Try moving the avatar in the given direction.
+    pub fn step(&mut self, dir: Dir) -> Vec<Event> {
+        let mut events = Vec::new();
+        if self.state.end {
+            return events;
+        }
+        let Some((x, y)) = self.target(dir) else {
+            events.push(Event::Blocked);
+            return events;
+        };
+        if let Some(sprite) = self.sprite_at(x, y) {
+            events.push(Event::Blocked);
+            if let Some(dialog) = &sprite.dialog {
+                events.push(Event::Dialog(dialog.clone()));
+            }
+            return events;
+        }
+        if self.is_wall(x, y) {
+            events.push(Event::Blocked);
+            return events;
+        }
+        self.state.pos_x = x;
+        self.state.pos_y = y;
+        events.push(Event::Moved);
+        self.pick_item(&mut events);
+        // Like in Bitsy, endings are checked before exits.
+        if !self.check_ending(&mut events) {
+            self.check_exit(&mut events);
+        }
+        events
+    }
+
+    /// Interpret the dialog with the given ID.
+    ///
+    /// Custom functions passed to [`Game::with_functions`] can be called from it.
+    pub fn dialog(&mut self, id: &str) -> Option<Interpreter<'_, Tokenizer<'_>>> {
+        let dialog = self.world.dialog(id)?;
+        self.state.dialog = id.to_string();
+        self.state.effects.clear();
+        let tokens = dialog.tokens().with_functions(&self.funcs);
+        let interpreter = Interpreter::new(tokens, &mut self.state);
+        Some(interpreter.with_functions(&self.funcs))
+    }
+
+    /// Get the position the avatar would move to, if it's inside the room.
+    fn target(&self, dir: Dir) -> Option<(u8, u8)> {
+        let room = self.room()?;
+        let height = room.tiles.len();
+        let width = room.tiles.first().map(Vec::len).unwrap_or_default();
+        let (x, y) = (self.state.pos_x, self.state.pos_y);
+        let (x, y) = match dir {
+            Dir::Up => (x, y.checked_sub(1)?),
+            Dir::Down => (x, y.checked_add(1)?),
+            Dir::Left => (x.checked_sub(1)?, y),
+            Dir::Right => (x.checked_add(1)?, y),
+        };
+        if usize::from(x) >= width || usize::from(y) >= height {
+            return None;
+        }
+        Some((x, y))
+    }
+
+    fn pick_item(&mut self, events: &mut Vec<Event>) {
+        let (x, y) = (self.state.pos_x, self.state.pos_y);
+        let room_id = &self.state.room;
+        let Some(room) = self.world.rooms.iter_mut().find(|r| r.id == *room_id) else {
+            return;
+        };
+        let Some(index) = room.items.iter().position(|i| i.x == x && i.y == y) else {
+            return;
+        };
+        let id = room.items.remove(index).id;
+        self.state.inventory.put(id.clone());
+        let dialog = self.world.item(&id).and_then(|i| i.dialog.clone());
+        events.push(Event::Item(id));
+        if let Some(dialog) = dialog {
+            events.push(Event::Dialog(dialog));
+        }
+    }
+
+    fn check_exit(&mut self, events: &mut Vec<Event>) {
+        let (x, y) = (self.state.pos_x, self.state.pos_y);
+        let Some(room) = self.room() else {
+            return;
+        };
+        let Some(exit) = room.exits.iter().find(|e| e.x == x && e.y == y) else {
+            return;
+        };
+        let exit = exit.clone();
+        self.state.room = exit.room.clone();
+        self.state.pos_x = exit.to_x;
+        self.state.pos_y = exit.to_y;
+        if let Some(room) = self.room() {
+            self.state.palette = room.palette.clone().unwrap_or_default();
+        }
+        events.push(Event::Exit(exit.room, exit.transition));
+        if let Some(dialog) = exit.dialog {
+            events.push(Event::Dialog(dialog));
+        }
+    }
+
+    /// End the game if there is an ending at the avatar position.
+    fn check_ending(&mut self, events: &mut Vec<Event>) -> bool {
+        let (x, y) = (self.state.pos_x, self.state.pos_y);
+        let Some(room) = self.room() else {
+            return false;
+        };
+        let Some(end) = room.endings.iter().find(|e| e.x == x && e.y == y) else {
+            return false;
+        };
+        events.push(Event::End(end.id.clone()));
+        self.state.end = true;
+        true
+    }
+}
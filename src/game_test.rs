@@ -0,0 +1,180 @@
+use crate::*;
+
+const GAME: &str = r#"test game
+
+PAL 0
+0,0,0
+255,255,255
+255,0,0
+
+PAL 1
+0,0,255
+255,255,255
+255,0,0
+
+ROOM 0
+0,0,0,0
+0,a,0,0
+0,0,0,0
+0,0,0,0
+ITM 0 2,0
+EXT 3,3 1 0,1 FX fade_w DLG 2
+PAL 0
+
+ROOM 1
+0,0
+0,0
+END 3 1,1
+PAL 1
+
+TIL a
+11111111
+NAME wall
+WAL true
+
+SPR A
+00011000
+POS 0 0,0
+ITM 0 1
+
+SPR a
+00000000
+DLG 0
+POS 0 0,2
+
+ITM 0
+00000000
+>
+00000001
+NAME tea
+DLG 1
+
+DLG 0
+meow
+
+DLG 1
+{a = a + 1}tea
+
+DLG 2
+bye
+
+DLG 3
+the end
+
+VAR a
+4
+
+"#;
+
+#[test]
+fn test_game_new() {
+    let game = Game::new(World::parse(GAME));
+    assert_eq!(game.state.room, "0");
+    assert_eq!((game.state.pos_x, game.state.pos_y), (0, 0));
+    assert_eq!(game.state.avatar, "A");
+    assert_eq!(game.state.palette, "0");
+    assert_eq!(game.state.inventory.get("0"), 1);
+    assert_eq!(game.state.vars.get("a"), &Val::I(4));
+    assert!(game.is_wall(1, 1));
+    assert!(!game.is_wall(0, 1));
+    assert_eq!(game.sprite_at(0, 2).unwrap().id, "a");
+    assert!(game.sprite_at(0, 0).is_none());
+}
+
+#[test]
+fn test_game_step() {
+    use Event::*;
+    let mut game = Game::new(World::parse(GAME));
+    assert_eq!(game.step(Dir::Up), vec![Blocked]);
+    assert_eq!(game.step(Dir::Left), vec![Blocked]);
+    assert_eq!(game.step(Dir::Down), vec![Moved]);
+    assert_eq!(game.step(Dir::Right), vec![Blocked]);
+    assert_eq!(game.step(Dir::Down), vec![Blocked, Dialog("0".to_string())]);
+    assert_eq!((game.state.pos_x, game.state.pos_y), (0, 1));
+
+    // Pick up an item.
+    game.step(Dir::Up);
+    game.step(Dir::Right);
+    let events = game.step(Dir::Right);
+    let exp = vec![Moved, Item("0".to_string()), Dialog("1".to_string())];
+    assert_eq!(events, exp);
+    assert_eq!(game.state.inventory.get("0"), 2);
+    assert!(game.room().unwrap().items.is_empty());
+    let words: Vec<_> = game.dialog("1").unwrap().collect();
+    assert_eq!(
+        words,
+        vec![Word::Text("tea".to_string(), Effects::default())]
+    );
+    assert_eq!(game.state.vars.get("a"), &Val::I(5));
+    game.step(Dir::Left);
+    assert_eq!(game.step(Dir::Right), vec![Moved]);
+
+    // Go through an exit.
+    game.step(Dir::Right);
+    game.step(Dir::Down);
+    game.step(Dir::Down);
+    let events = game.step(Dir::Down);
+    let exit = Exit("1".to_string(), Some(Transition::FadeW));
+    assert_eq!(events, vec![Moved, exit, Dialog("2".to_string())]);
+    assert_eq!(game.state.room, "1");
+    assert_eq!((game.state.pos_x, game.state.pos_y), (0, 1));
+    assert_eq!(game.state.palette, "1");
+
+    // Reach the ending.
+    assert_eq!(game.step(Dir::Down), vec![Blocked]);
+    assert_eq!(game.step(Dir::Right), vec![Moved, End("3".to_string())]);
+    assert!(game.state.end);
+    assert_eq!(game.step(Dir::Left), vec![]);
+}
+
+#[test]
+fn test_game_ending_before_exit() {
+    use Event::*;
+    // An ending on the arrival tile of the exit doesn't end the game.
+    let game = GAME.replace("END 3 1,1", "END 3 0,1");
+    let mut game = Game::new(World::parse(&game));
+    game.state.pos_x = 3;
+    game.state.pos_y = 2;
+    let events = game.step(Dir::Down);
+    let exit = Exit("1".to_string(), Some(Transition::FadeW));
+    assert_eq!(events, vec![Moved, exit, Dialog("2".to_string())]);
+    assert!(!game.state.end);
+
+    // The ending on the same tile as the exit wins.
+    let game = GAME.replace("PAL 0\n\nROOM 1", "END 3 3,3\nPAL 0\n\nROOM 1");
+    let mut game = Game::new(World::parse(&game));
+    game.state.pos_x = 3;
+    game.state.pos_y = 2;
+    assert_eq!(game.step(Dir::Down), vec![Moved, End("3".to_string())]);
+    assert_eq!(game.state.room, "0");
+    assert!(game.state.end);
+}
+
+#[test]
+fn test_game_functions() {
+    let mut funcs = Functions::new();
+    funcs.register("meow", |args, _| {
+        let n = args.first().map(Val::to_int).unwrap_or_default();
+        let text = "meow ".repeat(n as usize);
+        vec![Word::Text(text, Effects::default())].into()
+    });
+    let world = World::parse(&GAME.replace("\nmeow\n", "\n{meow 2}\n"));
+    let mut game = Game::new(world).with_functions(funcs);
+    let words: Vec<_> = game.dialog("0").unwrap().collect();
+    let exp = vec![Word::Text("meow meow ".to_string(), Effects::default())];
+    assert_eq!(words, exp);
+}
+
+#[test]
+fn test_game_frame() {
+    let mut game = Game::new(World::parse(GAME));
+    let frames = &game.world.item("0").unwrap().frames.clone();
+    assert_eq!(game.frame(frames), Some(&frames[0]));
+    game.tick(399);
+    assert_eq!(game.frame(frames), Some(&frames[0]));
+    game.tick(1);
+    assert_eq!(game.frame(frames), Some(&frames[1]));
+    game.tick(400);
+    assert_eq!(game.frame(frames), Some(&frames[0]));
+    assert_eq!(game.frame(&[]), None);
+}
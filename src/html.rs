@@ -0,0 +1,98 @@
+use crate::*;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// A `<script>` tag found in the HTML.
+struct Script<'a> {
+    /// Everything between "<script" and ">".
+    attrs: &'a str,
+    /// Everything between the opening and the closing tag.
+    body: &'a str,
+}
+
+impl<'a> Script<'a> {
+    /// Get the value of the given attribute.
+    fn attr(&self, name: &str) -> Option<&'a str> {
+        let mut attrs = self.attrs;
+        while let Some(start) = attrs.find(name) {
+            let before = attrs[..start].chars().next_back();
+            attrs = &attrs[start + name.len()..];
+            if before.is_some_and(|ch| !ch.is_ascii_whitespace()) {
+                continue;
+            }
+            let Some(val) = attrs.trim_ascii_start().strip_prefix('=') else {
+                continue;
+            };
+            let val = val.trim_ascii_start();
+            let quote = val.chars().next()?;
+            if quote != '"' && quote != '\'' {
+                let end = val.find(|ch: char| ch.is_ascii_whitespace());
+                return Some(&val[..end.unwrap_or(val.len())]);
+            }
+            let val = &val[1..];
+            let end = val.find(quote)?;
+            return Some(&val[..end]);
+        }
+        None
+    }
+
+    /// Check if the script type contains the given marker, like "bitsyGameData".
+    fn has_type(&self, kind: &str) -> bool {
+        self.attr("type").is_some_and(|t| t.contains(kind))
+    }
+}
+
+/// Iterate over all `<script>` tags in the HTML.
+fn scripts(html: &str) -> impl Iterator<Item = Script<'_>> {
+    let mut rest = html;
+    core::iter::from_fn(move || {
+        let start = rest.find("<script")?;
+        rest = &rest[start + 7..];
+        let end = rest.find('>')?;
+        let attrs = &rest[..end];
+        rest = &rest[end + 1..];
+        let end = rest.find("</script>").unwrap_or(rest.len());
+        let body = &rest[..end];
+        rest = &rest[end..];
+        Some(Script { attrs, body })
+    })
+}
+
+/// Remove the line break that goes right after the opening tag.
+fn strip_newline(body: &str) -> &str {
+    let body = body.strip_prefix('\r').unwrap_or(body);
+    body.strip_prefix('\n').unwrap_or(body)
+}
+
+/// Find the game data embedded into a Bitsy game exported as HTML.
+pub fn extract_game_data(html: &str) -> Option<&str> {
+    let script = scripts(html).find(|s| s.has_type("bitsyGameData"))?;
+    Some(strip_newline(script.body))
+}
+
+/// Find all fonts embedded into a Bitsy game exported as HTML.
+///
+/// Font names are taken from the script IDs.
+pub fn extract_fonts(html: &str) -> Vec<FontData> {
+    let mut fonts = Vec::new();
+    for script in scripts(html) {
+        if !script.has_type("bitsyFontData") {
+            continue;
+        }
+        let name = script.attr("id").unwrap_or_default();
+        let data = strip_newline(script.body).trim_ascii_end();
+        fonts.push(FontData {
+            name: name.to_string(),
+            data: data.to_string(),
+        });
+    }
+    fonts
+}
+
+impl World {
+    /// Parse the game data embedded into a Bitsy game exported as HTML.
+    pub fn from_html(html: &str) -> Option<Self> {
+        let data = extract_game_data(html)?;
+        Some(Self::parse(data))
+    }
+}
@@ -0,0 +1,52 @@
+use crate::*;
+
+const HTML: &str = r#"<!DOCTYPE HTML>
+<html>
+<head>
+<title>my game</title>
+<script type="text/javascript">
+var x = "<b>";
+</script>
+<script type="text/bitsyFontData" id='ascii_small'>
+FONT ascii_small
+
+SIZE 6 8
+
+</script>
+<script type="text/bitsyGameData" id="exportedGameData">
+my game
+
+# BITSY VERSION 8.12
+
+DLG 0
+hello world
+
+</script>
+<script type="bitsyFontData" id=tiny>FONT tiny</script>
+</head>
+<body></body>
+</html>
+"#;
+
+#[test]
+fn test_html_game_data() {
+    let data = extract_game_data(HTML).unwrap();
+    assert!(data.starts_with("my game\n\n# BITSY"));
+    assert!(data.ends_with("hello world\n\n"));
+    let world = World::from_html(HTML).unwrap();
+    assert_eq!(world.title, "my game");
+    assert_eq!(world.dialog("0").unwrap().text, "hello world");
+
+    assert_eq!(extract_game_data("<html></html>"), None);
+    assert_eq!(World::from_html("<script>hi</script>"), None);
+}
+
+#[test]
+fn test_html_fonts() {
+    let fonts = extract_fonts(HTML);
+    assert_eq!(fonts.len(), 2);
+    assert_eq!(fonts[0].name, "ascii_small");
+    assert_eq!(fonts[0].data, "FONT ascii_small\n\nSIZE 6 8");
+    assert_eq!(fonts[1].name, "tiny");
+    assert_eq!(fonts[1].data, "FONT tiny");
+}
@@ -1,116 +1,495 @@
 use crate::*;
+use alloc::collections::VecDeque;
 use alloc::format;
+use alloc::rc::Rc;
 use alloc::string::String;
 use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::discriminant;
 
 #[derive(Debug, PartialEq)]
 pub enum Word {
-    Text(String, TextEffect),
+    Text(String, Effects),
     Sprite(ID),
     Tile(ID),
     Item(ID),
     LineBreak,
     PageBreak,
+    /// A side effect happened at this point of the dialog.
+    ///
+    /// The state is already updated when the event is emitted.
+    Event(DialogEvent),
 }
 
+/// A side effect of the dialog that the host might need to react to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DialogEvent {
+    /// The player was moved to the given room and position.
+    Exit(ID, u8, u8, Option<Transition>),
+    /// The game is over.
+    End,
+    /// The current palette was changed.
+    Pal(ID),
+    /// The avatar now looks like the given sprite.
+    Ava(ID),
+    /// The tune should be played. The tune "0" stops the music.
+    Tune(ID),
+    /// The blip sound effect should be played.
+    Blip(ID),
+}
+
+/// Text effects applied to a word.
+///
+/// Effects of different kinds combine. For nested colors, the innermost one wins.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Effects {
+    pub wavy: bool,
+    pub shaky: bool,
+    pub rainbow: bool,
+    /// Palette color index.
+    pub color: Option<u8>,
+}
+
+impl Effects {
+    /// Add the effect to the set.
+    pub fn with(mut self, eff: TextEffect) -> Self {
+        match eff {
+            TextEffect::None => {}
+            TextEffect::Wavy => self.wavy = true,
+            TextEffect::Shaky => self.shaky = true,
+            TextEffect::Rainbow => self.rainbow = true,
+            TextEffect::Color(c) => self.color = Some(c),
+        }
+        self
+    }
+
+    /// Check if there are no effects applied.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Collect effects from the stack of opened effect tags.
+    fn from_stack(stack: &[TextEffect]) -> Self {
+        stack
+            .iter()
+            .fold(Self::default(), |effs, eff| effs.with(*eff))
+    }
+}
+
+/// How deep string values printed by `{say}` can be nested into each other.
+const MAX_SAY_DEPTH: u8 = 8;
+
 pub struct Interpreter<'a, T: Iterator<Item = Token>> {
     pub tokens: T,
     pub state: &'a mut State,
+    /// Tokens of the picked block items and printed strings that are yet to be interpreted.
+    stack: Vec<Frame>,
+    funcs: Option<&'a Functions>,
+    /// The visits scope of the dialog's own blocks.
+    root: Rc<str>,
+    /// Words returned by a custom function that are yet to be emitted.
+    words: VecDeque<Word>,
+}
+
+/// Tokens or nodes that are interpreted in place of a block or a `{say}` tag.
+struct Frame {
+    items: Items,
+    /// How many `{say}` tags the tokens are nested into.
+    depth: u8,
+    /// The key under which visits of list blocks are counted.
+    ///
+    /// It's the dialog ID for the dialog's own blocks. Strings printed by `{say}`
+    /// are tokenized separately, so their blocks are counted under the parent scope
+    /// followed by the ID of the `{say}` tag.
+    scope: Rc<str>,
+    /// The effect to close when all items of the frame are interpreted.
+    close: Option<TextEffect>,
+    /// How many effects were opened when the frame was pushed.
+    ///
+    /// Effects left open by a block item or a printed string are closed at its end,
+    /// the same way the parser closes them at the end of the item.
+    effects: usize,
+}
+
+enum Items {
+    Tokens(vec::IntoIter<Token>),
+    Nodes(vec::IntoIter<Node>),
+}
+
+/// The next thing to interpret.
+enum Next {
+    Token(Token),
+    Node(Node),
+}
+
+impl<'a> Interpreter<'a, core::iter::Empty<Token>> {
+    /// Interpret the syntax tree produced by [`parse`].
+    pub fn from_nodes(nodes: Vec<Node>, state: &'a mut State) -> Self {
+        let mut interpreter = Self::new(core::iter::empty(), state);
+        let scope = interpreter.root.clone();
+        interpreter.push_nodes(nodes, 0, scope, None);
+        interpreter
+    }
+}
+
+impl<'a, T: Iterator<Item = Token>> Interpreter<'a, T> {
+    pub fn new(tokens: T, state: &'a mut State) -> Self {
+        Self {
+            tokens,
+            root: Rc::from(state.dialog.as_str()),
+            state,
+            stack: Vec::new(),
+            funcs: None,
+            words: VecDeque::new(),
+        }
+    }
+
+    /// Use the given custom functions for unknown tags and calls in expressions.
+    pub fn with_functions(mut self, funcs: &'a Functions) -> Self {
+        self.funcs = Some(funcs);
+        self
+    }
+
+    /// Get the next token or node, either from a picked block item or from the input.
+    fn next_token(&mut self) -> Option<Next> {
+        while let Some(frame) = self.stack.last_mut() {
+            let next = match &mut frame.items {
+                Items::Tokens(tokens) => tokens.next().map(Next::Token),
+                Items::Nodes(nodes) => nodes.next().map(Next::Node),
+            };
+            if next.is_some() {
+                return next;
+            }
+            if let Some(frame) = self.stack.pop() {
+                self.state.effects.truncate(frame.effects);
+                if let Some(eff) = frame.close {
+                    handle_close_tag(Tag::Eff(eff), self.state);
+                }
+            }
+        }
+        self.tokens.next().map(Next::Token)
+    }
+
+    /// The `{say}` nesting depth of the last token returned by `next_token`.
+    fn depth(&self) -> u8 {
+        self.stack.last().map(|f| f.depth).unwrap_or_default()
+    }
+
+    /// The visits scope of the last token returned by `next_token`.
+    fn scope(&self) -> Rc<str> {
+        match self.stack.last() {
+            Some(frame) => frame.scope.clone(),
+            None => self.root.clone(),
+        }
+    }
+
+    /// Interpret the given tokens before continuing with the current ones.
+    fn push(&mut self, tokens: Vec<Token>, depth: u8, scope: Rc<str>) {
+        self.stack.push(Frame {
+            items: Items::Tokens(tokens.into_iter()),
+            depth,
+            scope,
+            close: None,
+            effects: self.state.effects.len(),
+        });
+    }
+
+    /// Interpret the given nodes before continuing with the current ones.
+    ///
+    /// If the effect is given, it gets closed after the nodes.
+    fn push_nodes(
+        &mut self,
+        nodes: Vec<Node>,
+        depth: u8,
+        scope: Rc<str>,
+        close: Option<TextEffect>,
+    ) {
+        self.stack.push(Frame {
+            items: Items::Nodes(nodes.into_iter()),
+            depth,
+            scope,
+            close,
+            effects: self.state.effects.len(),
+        });
+    }
+
+    /// Handle an opening tag or a function without a body.
+    fn open_tag(&mut self, tag: Tag) -> Option<Word> {
+        match tag {
+            Tag::Seq(id, kind, items) => {
+                let scope = self.scope();
+                if let Some(item) = pick_item(&scope, id, kind, items, self.state) {
+                    self.push(item, self.depth(), scope);
+                }
+                None
+            }
+            Tag::If(branches) => {
+                if let Some(branch) = pick_branch(branches, self.state, self.funcs) {
+                    self.push(branch, self.depth(), self.scope());
+                }
+                None
+            }
+            Tag::Say(id, expr) => self.say(id, expr),
+            Tag::Unknown(name, args) => self.call(&name, &args),
+            tag => handle_open_tag(tag, self.state, self.funcs),
+        }
+    }
+
+    /// Handle a node of the syntax tree.
+    fn node(&mut self, node: Node) -> Option<Word> {
+        match node {
+            Node::Word(t) => {
+                let effects = Effects::from_stack(&self.state.effects);
+                Some(Word::Text(t, effects))
+            }
+            Node::Func(tag) => self.open_tag(tag),
+            Node::Eff(eff, nodes) => {
+                self.state.effects.push(eff);
+                self.push_nodes(nodes, self.depth(), self.scope(), Some(eff));
+                None
+            }
+            Node::Seq(id, kind, items) => {
+                let scope = self.scope();
+                if let Some(item) = pick_item(&scope, id, kind, items, self.state) {
+                    self.push_nodes(item, self.depth(), scope, None);
+                }
+                None
+            }
+            Node::If(branches) => {
+                if let Some(branch) = pick_branch(branches, self.state, self.funcs) {
+                    self.push_nodes(branch, self.depth(), self.scope(), None);
+                }
+                None
+            }
+        }
+    }
+
+    /// Print the value. Strings are tokenized and interpreted as dialog text.
+    fn say(&mut self, id: u16, expr: Expr) -> Option<Word> {
+        let depth = self.depth();
+        let s = match eval_expr(expr, self.state, self.funcs) {
+            Val::S(s) if depth < MAX_SAY_DEPTH => s,
+            val => {
+                let effects = Effects::from_stack(&self.state.effects);
+                return Some(Word::Text(val.to_string(), effects));
+            }
+        };
+        // Dialog IDs can't contain line breaks, so the scope can't clash with them.
+        let scope = Rc::from(format!("{}\n{id}", self.scope()));
+        let tokens = Tokenizer::new(&s).collect();
+        self.push(tokens, depth + 1, scope);
+        None
+    }
+
+    /// Call the custom function used as a tag.
+    fn call(&mut self, name: &str, args: &str) -> Option<Word> {
+        let funcs = self.funcs?;
+        if !funcs.contains(name) {
+            return None;
+        }
+        let args = eval_args(parse_args(args), self.state, self.funcs);
+        if let Some(FuncResult::Words(words)) = funcs.call(name, &args, self.state) {
+            self.words.extend(words);
+        }
+        self.words.pop_front()
+    }
 }
 
 impl<'a, T: Iterator<Item = Token>> Iterator for Interpreter<'a, T> {
     type Item = Word;
 
     fn next(&mut self) -> Option<Self::Item> {
-        interpret(&mut self.tokens, self.state)
+        if let Some(word) = self.words.pop_front() {
+            return Some(word);
+        }
+        while let Some(next) = self.next_token() {
+            let maybe_word = match next {
+                Next::Token(Token::OpenTag(tag)) => self.open_tag(tag),
+                Next::Token(Token::CloseTag(tag)) => handle_close_tag(tag, self.state),
+                Next::Token(Token::Word(t)) => {
+                    let effects = Effects::from_stack(&self.state.effects);
+                    Some(Word::Text(t, effects))
+                }
+                Next::Node(node) => self.node(node),
+            };
+            if let Some(word) = maybe_word {
+                return Some(word);
+            }
+        }
+        None
     }
 }
 
-pub fn interpret<T>(tokens: &mut T, state: &mut State) -> Option<Word>
-where
-    T: Iterator<Item = Token>,
-{
-    for token in tokens.by_ref() {
-        let maybe_word = match token {
-            Token::OpenTag(tag) => handle_open_tag(tag, state),
-            Token::CloseTag(tag) => handle_close_tag(tag, state),
-            Token::Word(t) => Some(Word::Text(t, state.effect)),
+/// Pick the item of a list block to show and record the visit.
+fn pick_item<I>(
+    scope: &str,
+    id: u16,
+    kind: SeqKind,
+    items: Vec<I>,
+    state: &mut State,
+) -> Option<I> {
+    if items.is_empty() {
+        return None;
+    }
+    let key = (scope.to_string(), id);
+    let visits = state.visits.entry(key).or_default();
+    let n = *visits as usize;
+    *visits = visits.saturating_add(1);
+    let len = items.len();
+    let index = match kind {
+        SeqKind::Sequence => n.min(len - 1),
+        SeqKind::Cycle => n % len,
+        SeqKind::Shuffle => {
+            // Each round of visits shows every item once, in a new random order.
+            let round = (n / len) as u32;
+            let mut order: Vec<usize> = (0..len).collect();
+            let mut rng = state.seed ^ (u32::from(id) << 16) ^ round;
+            for i in (1..len).rev() {
+                rng = next_rand(rng);
+                order.swap(i, rng as usize % (i + 1));
+            }
+            order[n % len]
+        }
+    };
+    items.into_iter().nth(index)
+}
+
+/// Pick the first branch of a conditional block which condition is true.
+fn pick_branch<I>(
+    branches: Vec<(Option<Expr>, I)>,
+    state: &mut State,
+    funcs: Option<&Functions>,
+) -> Option<I> {
+    for (cond, body) in branches {
+        let Some(cond) = cond else {
+            return Some(body);
         };
-        if let Some(word) = maybe_word {
-            return Some(word);
+        if eval_expr(cond, state, funcs).is_truthy() {
+            return Some(body);
         }
     }
     None
 }
 
-fn handle_open_tag(tag: Tag, state: &mut State) -> Option<Word> {
+/// Generate the next pseudo-random number using xorshift.
+fn next_rand(mut x: u32) -> u32 {
+    if x == 0 {
+        x = 0x9E37_79B9;
+    }
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    x
+}
+
+fn handle_open_tag(tag: Tag, state: &mut State, funcs: Option<&Functions>) -> Option<Word> {
     match tag {
         Tag::Br => return Some(Word::LineBreak),
         Tag::Pg => return Some(Word::PageBreak),
-        Tag::Eff(eff) => {
-            if state.effect != TextEffect::None {
-                state.effect = TextEffect::None;
-            } else {
-                state.effect = eff
-            }
-        }
-        Tag::End => state.end = true,
-        Tag::Say(expr) => {
-            let val = eval_expr(expr, state);
-            // TODO(@orsinium): String values can contain multiple words
-            // and even subnodes. We need to parse them with Tokenizer.
-            let s = val_to_string(&val);
-            return Some(Word::Text(s, state.effect));
+        Tag::Eff(eff) => state.effects.push(eff),
+        Tag::End => {
+            state.end = true;
+            return Some(Word::Event(DialogEvent::End));
         }
         Tag::DrwT(id) => return Some(Word::Tile(id)),
         Tag::DrwS(id) => return Some(Word::Sprite(id)),
         Tag::DrwI(id) => return Some(Word::Item(id)),
-        Tag::Pal(pal) => state.palette = pal,
-        Tag::Ava(id) => state.avatar = id,
-        Tag::Exit(room, x, y) => {
-            state.room = room;
+        Tag::Pal(pal) => {
+            state.palette = pal.clone();
+            return Some(Word::Event(DialogEvent::Pal(pal)));
+        }
+        Tag::Ava(id) => {
+            state.avatar = id.clone();
+            return Some(Word::Event(DialogEvent::Ava(id)));
+        }
+        Tag::Tune(id) => return Some(Word::Event(DialogEvent::Tune(id))),
+        Tag::Blip(id) => return Some(Word::Event(DialogEvent::Blip(id))),
+        Tag::Exit(room, x, y, fx) => {
+            state.room = room.clone();
             state.pos_x = x;
             state.pos_y = y;
+            return Some(Word::Event(DialogEvent::Exit(room, x, y, fx)));
         }
         Tag::Set(name, expr) => {
-            let val = eval_expr(expr, state);
+            let val = eval_expr(expr, state, funcs);
             state.vars.set(name, val);
         }
-        Tag::Unknown(_, _) => {}
+        Tag::Item(id, count) => {
+            if let Some(count) = count {
+                let count = eval_expr(count, state, funcs);
+                state.inventory.set(id, val_to_count(&count));
+            }
+        }
+        Tag::Give(id, count) => {
+            let count = eval_expr(count, state, funcs);
+            state.inventory.add(id, val_to_count(&count));
+        }
+        Tag::Take(id, count) => {
+            let count = eval_expr(count, state, funcs);
+            state.inventory.remove(id, val_to_count(&count));
+        }
+        Tag::Say(..) | Tag::Seq(..) | Tag::If(_) | Tag::Unknown(_, _) => {}
     };
     None
 }
 
+/// Handle a closing tag. Closes the innermost opened effect of the same kind.
 fn handle_close_tag(tag: Tag, state: &mut State) -> Option<Word> {
-    if let Tag::Eff(_) = tag {
-        state.effect = TextEffect::None
+    if let Tag::Eff(eff) = tag {
+        let kind = discriminant(&eff);
+        let found = state.effects.iter().rposition(|e| discriminant(e) == kind);
+        if let Some(index) = found {
+            state.effects.remove(index);
+        }
     };
     None
 }
 
-fn eval_expr(expr: Expr, state: &mut State) -> Val {
+fn eval_expr(expr: Expr, state: &mut State, funcs: Option<&Functions>) -> Val {
     match expr {
-        Expr::SimpleExpr(expr) => eval_simple_expr(expr, state),
+        Expr::SimpleExpr(expr) => eval_simple_expr(expr, state, funcs),
         Expr::BinOp(op, lhs, rhs) => {
-            let lhs = eval_simple_expr(lhs, state);
-            let rhs = eval_simple_expr(rhs, state);
+            let lhs = eval_expr(*lhs, state, funcs);
+            let rhs = eval_expr(*rhs, state, funcs);
             eval_bin_op(op, lhs, rhs)
         }
+        Expr::Neg(expr) => match eval_expr(*expr, state, funcs) {
+            Val::I(i) => Val::I(i.wrapping_neg()),
+            Val::F(f) => Val::F(-f),
+            val => val,
+        },
     }
 }
 
-fn eval_simple_expr(expr: SimpleExpr, state: &mut State) -> Val {
+fn eval_simple_expr(expr: SimpleExpr, state: &mut State, funcs: Option<&Functions>) -> Val {
     match expr {
         SimpleExpr::Var(name) => state.vars.get(&name).clone(),
         SimpleExpr::Item(name) => Val::I(state.inventory.get(&name) as i16),
         SimpleExpr::Val(val) => val,
+        SimpleExpr::Call(name, args) => {
+            let Some(funcs) = funcs else {
+                return Val::Undef;
+            };
+            let args = eval_args(args, state, Some(funcs));
+            match funcs.call(&name, &args, state) {
+                Some(FuncResult::Val(val)) => val,
+                _ => Val::Undef,
+            }
+        }
     }
 }
 
+fn eval_args(args: Vec<Expr>, state: &mut State, funcs: Option<&Functions>) -> Vec<Val> {
+    args.into_iter()
+        .map(|arg| eval_expr(arg, state, funcs))
+        .collect()
+}
+
 fn eval_bin_op(op: BinOp, lhs: Val, rhs: Val) -> Val {
     match op {
         BinOp::Mul => match (lhs, rhs) {
-            (Val::I(a), Val::I(b)) => Val::I(a * b),
+            (Val::I(a), Val::I(b)) => Val::I(a.wrapping_mul(b)),
             (Val::I(a), Val::F(b)) => Val::F(a as f32 * b),
             (Val::F(a), Val::I(b)) => Val::F(a * b as f32),
             (Val::F(a), Val::F(b)) => Val::F(a * b),
@@ -118,7 +497,10 @@ fn eval_bin_op(op: BinOp, lhs: Val, rhs: Val) -> Val {
             (a, _) => a,
         },
         BinOp::Div => match (lhs, rhs) {
-            (Val::I(a), Val::I(b)) => Val::I(a / b),
+            (Val::I(a), Val::I(b)) => match a.checked_div(b) {
+                Some(res) => Val::I(res),
+                None => Val::F(a as f32 / b as f32),
+            },
             (Val::I(a), Val::F(b)) => Val::F(a as f32 / b),
             (Val::F(a), Val::I(b)) => Val::F(a / b as f32),
             (Val::F(a), Val::F(b)) => Val::F(a / b),
@@ -126,7 +508,7 @@ fn eval_bin_op(op: BinOp, lhs: Val, rhs: Val) -> Val {
             (a, _) => a,
         },
         BinOp::Add => match (lhs, rhs) {
-            (Val::I(a), Val::I(b)) => Val::I(a + b),
+            (Val::I(a), Val::I(b)) => Val::I(a.wrapping_add(b)),
             (Val::I(a), Val::F(b)) => Val::F(a as f32 + b),
             (Val::F(a), Val::I(b)) => Val::F(a + b as f32),
             (Val::S(a), Val::S(b)) => Val::S(alloc::format!("{a}{b}")),
@@ -135,7 +517,7 @@ fn eval_bin_op(op: BinOp, lhs: Val, rhs: Val) -> Val {
             (_, b) => b,
         },
         BinOp::Sub => match (lhs, rhs) {
-            (Val::I(a), Val::I(b)) => Val::I(a - b),
+            (Val::I(a), Val::I(b)) => Val::I(a.wrapping_sub(b)),
             (Val::I(a), Val::F(b)) => Val::F(a as f32 - b),
             (Val::F(a), Val::I(b)) => Val::F(a - b as f32),
             (Val::F(a), Val::F(b)) => Val::F(a - b),
@@ -195,11 +577,7 @@ fn eval_bin_op(op: BinOp, lhs: Val, rhs: Val) -> Val {
     }
 }
 
-fn val_to_string(val: &Val) -> String {
-    match val {
-        Val::Undef => "0".to_string(),
-        Val::I(i) => format!("{i}"),
-        Val::S(s) => s.to_string(),
-        Val::F(f) => format!("{f}"),
-    }
+/// Convert the value into an item count. Negative and non-numeric values are 0.
+fn val_to_count(val: &Val) -> u16 {
+    val.to_int().max(0) as u16
 }
@@ -3,37 +3,392 @@ use crate::*;
 #[test]
 fn test_interpreter() {
     let (words, _) = run("hello");
-    let exp = vec![Word::Text("hello".to_string(), TextEffect::None)];
+    let exp = vec![Word::Text("hello".to_string(), Effects::default())];
     assert_eq!(words, exp);
 
     let (words, _) = run("oh{br}hi");
     let exp = vec![
-        Word::Text("oh".to_string(), TextEffect::None),
+        Word::Text("oh".to_string(), Effects::default()),
         Word::LineBreak,
-        Word::Text("hi".to_string(), TextEffect::None),
+        Word::Text("hi".to_string(), Effects::default()),
     ];
     assert_eq!(words, exp);
 
     let (words, _) = run("{a = 14}{say a}");
-    let exp = vec![Word::Text("14".to_string(), TextEffect::None)];
+    let exp = vec![Word::Text("14".to_string(), Effects::default())];
     assert_eq!(words, exp);
 
     let (words, _) = run("{say 6 + 7}");
-    let exp = vec![Word::Text("13".to_string(), TextEffect::None)];
+    let exp = vec![Word::Text("13".to_string(), Effects::default())];
     assert_eq!(words, exp);
 
     let (words, _) = run("{say 6 - 9}");
-    let exp = vec![Word::Text("-3".to_string(), TextEffect::None)];
+    let exp = vec![Word::Text("-3".to_string(), Effects::default())];
     assert_eq!(words, exp);
 }
 
+#[test]
+fn test_interpreter_effects() {
+    let none = Effects::default();
+    let wavy = none.with(TextEffect::Wavy);
+    let clr1 = none.with(TextEffect::Color(1));
+    let clr2 = none.with(TextEffect::Color(2));
+
+    let (words, _) = run("{wvy}{clr2}hi{/clr2}{/wvy}");
+    assert_eq!(words, vec![text("hi", wavy.with(TextEffect::Color(2)))]);
+
+    let (words, _) = run("a {wvy}b {shk}c{/wvy} d{/shk} e");
+    let exp = vec![
+        text("a ", none),
+        text("b ", wavy),
+        text("c", wavy.with(TextEffect::Shaky)),
+        text(" d", none.with(TextEffect::Shaky)),
+        text(" e", none),
+    ];
+    assert_eq!(words, exp);
+
+    let (words, _) = run("{clr1}a {clr2}b{/clr2} c{/clr1} d");
+    let exp = vec![
+        text("a ", clr1),
+        text("b", clr2),
+        text(" c", clr1),
+        text(" d", none),
+    ];
+    assert_eq!(words, exp);
+
+    let (words, _) = run("{rbw}{a = 1}{say a}{/rbw}{/rbw}{say a}");
+    let exp = vec![text("1", none.with(TextEffect::Rainbow)), text("1", none)];
+    assert_eq!(words, exp);
+    assert!(none.is_empty());
+    assert!(!wavy.is_empty());
+}
+
+#[test]
+fn test_interpreter_unbalanced_item() {
+    // An effect left open in a block item is closed at the end of the item,
+    // both when interpreting tokens and nodes.
+    let dialog = "{cycle\n - {wvy}a\n - b\n}c";
+    let wavy = Effects::default().with(TextEffect::Wavy);
+    let exp = vec![text("a", wavy), text("c", Effects::default())];
+    let (words, state) = run(dialog);
+    assert_eq!(words, exp);
+    assert!(state.effects.is_empty());
+
+    let mut state = State::default();
+    let words: Vec<_> = Interpreter::from_nodes(parse(dialog), &mut state).collect();
+    assert_eq!(words, exp);
+    assert!(state.effects.is_empty());
+}
+
+#[test]
+fn test_interpreter_say_string() {
+    let none = Effects::default();
+    let mut state = State::default();
+    state
+        .vars
+        .set("a".to_string(), Val::S("hi{br}there".to_string()));
+    state.vars.set(
+        "b".to_string(),
+        Val::S("{wvy}{say a}{/wvy} you".to_string()),
+    );
+    let tokens = Tokenizer::new("{say b}!");
+    let words: Vec<_> = Interpreter::new(tokens, &mut state).collect();
+    let wavy = none.with(TextEffect::Wavy);
+    let exp = vec![
+        text("hi", wavy),
+        Word::LineBreak,
+        text("there", wavy),
+        text(" you", none),
+        text("!", none),
+    ];
+    assert_eq!(words, exp);
+
+    let tokens = Tokenizer::new("{say b}");
+    let words: Vec<_> = Interpreter::new(tokens, &mut state).collect();
+    assert_eq!(words, run("{wvy}hi{br}there{/wvy} you").0);
+
+    // Recursion is limited, the innermost value is printed as is.
+    state
+        .vars
+        .set("r".to_string(), Val::S("x{say r}".to_string()));
+    assert_eq!(say("{say r}", &mut state), "xxxxxxxxx{say r}");
+}
+
+#[test]
+fn test_interpreter_say_blocks() {
+    // Blocks in printed strings don't share visits with blocks of the dialog.
+    let mut state = State::default();
+    let cycle = "{cycle\n - x\n - y\n}";
+    state.vars.set("s".to_string(), Val::S(cycle.to_string()));
+    let dialog = "{sequence\n - a\n - b\n}{say s}";
+    assert_eq!(say(dialog, &mut state), "ax");
+    assert_eq!(say(dialog, &mut state), "by");
+    assert_eq!(say(dialog, &mut state), "bx");
+
+    // The same string printed from another dialog has its own visits.
+    state.dialog = "other".to_string();
+    assert_eq!(say("{say s}", &mut state), "x");
+}
+
+#[test]
+fn test_interpreter_say_visits() {
+    // Visits of blocks in printed strings are keyed on the say tag, not on the text.
+    let mut state = State::default();
+    for i in 0..10 {
+        let text = alloc::format!("{{cycle\n - {i}\n - y\n}}");
+        state.vars.set("s".to_string(), Val::S(text));
+        say("{say s}", &mut state);
+    }
+    assert_eq!(state.visits.len(), 1);
+    assert_eq!(state.visits.values().next(), Some(&10));
+}
+
+#[test]
+fn test_interpreter_functions() {
+    let mut funcs = Functions::new();
+    funcs.register("double", |args, _| match args {
+        [Val::I(i)] => Val::I(i * 2).into(),
+        _ => Val::Undef.into(),
+    });
+    funcs.register("greet", |args, state| {
+        state.vars.set("greeted".to_string(), Val::I(1));
+        let name = match args {
+            [Val::S(name)] => name.clone(),
+            _ => "stranger".to_string(),
+        };
+        vec![
+            text("hi,", Effects::default()),
+            text(&name, Effects::default()),
+        ]
+        .into()
+    });
+    assert!(funcs.contains("double"));
+    assert!(!funcs.contains("triple"));
+
+    let mut state = State::default();
+    let tokens = Tokenizer::new(r#"{greet "bob"}!{a = {double 3} + 1}{say a}{triple}{double 2}"#);
+    let words: Vec<_> = Interpreter::new(tokens, &mut state)
+        .with_functions(&funcs)
+        .collect();
+    let exp = vec![
+        text("hi,", Effects::default()),
+        text("bob", Effects::default()),
+        text("!", Effects::default()),
+        text("7", Effects::default()),
+    ];
+    assert_eq!(words, exp);
+    assert_eq!(state.vars.get("greeted"), &Val::I(1));
+
+    // Unknown functions are ignored, calls in expressions are undefined.
+    let mut state = State::default();
+    assert_eq!(say("{greet}{a = {double 3}}{say a}", &mut state), "0");
+    let mut state = State::default();
+    let tokens = Tokenizer::new("{a = {greet}}{say a}{greet}");
+    let words: Vec<_> = Interpreter::new(tokens, &mut state)
+        .with_functions(&funcs)
+        .collect();
+    let exp = vec![
+        text("0", Effects::default()),
+        text("hi,", Effects::default()),
+        text("stranger", Effects::default()),
+    ];
+    assert_eq!(words, exp);
+
+    // Built-in tags win over functions with the same name.
+    let mut funcs = Functions::new();
+    for name in ["item", "give", "take"] {
+        funcs.register(name, |_, state| {
+            state.vars.set("called".to_string(), Val::I(1));
+            vec![text("no", Effects::default())].into()
+        });
+    }
+    let mut state = State::default();
+    let tokens = Tokenizer::new(r#"{give "key" 3}{take "key"}{say {item "key"}}"#);
+    let words: Vec<_> = Interpreter::new(tokens, &mut state)
+        .with_functions(&funcs)
+        .collect();
+    assert_eq!(words, vec![text("2", Effects::default())]);
+    assert_eq!(state.vars.get("called"), &Val::Undef);
+}
+
+#[test]
+fn test_interpreter_events() {
+    let none = Effects::default();
+    let (words, state) =
+        run(r#"bye{pal "1"}{ava "B"}{exit "2",3,4,"tunnel"} now{tune "1"}{blip "2"}{end}"#);
+    let exp = vec![
+        text("bye", none),
+        Word::Event(DialogEvent::Pal("1".to_string())),
+        Word::Event(DialogEvent::Ava("B".to_string())),
+        Word::Event(DialogEvent::Exit(
+            "2".to_string(),
+            3,
+            4,
+            Some(Transition::Tunnel),
+        )),
+        text(" now", none),
+        Word::Event(DialogEvent::Tune("1".to_string())),
+        Word::Event(DialogEvent::Blip("2".to_string())),
+        Word::Event(DialogEvent::End),
+    ];
+    assert_eq!(words, exp);
+    assert_eq!(state.palette, "1");
+    assert_eq!(state.avatar, "B");
+    assert_eq!((state.room.as_str(), state.pos_x, state.pos_y), ("2", 3, 4));
+    assert!(state.end);
+}
+
+#[test]
+fn test_interpreter_expr() {
+    let mut state = State::default();
+    state.vars.set("b".to_string(), Val::I(3));
+    assert_eq!(say("{a = 1}{a = a + b * 2}{say a}", &mut state), "7");
+    assert_eq!(say("{say (a + 1) * 2}", &mut state), "16");
+    assert_eq!(say("{say -(a - 10) / 3}", &mut state), "1");
+    assert_eq!(say("{say 10 - 4 - 3}", &mut state), "3");
+    assert_eq!(say("{say 1 + 2 == 3}", &mut state), "1");
+    assert_eq!(say("{say 1.5 * 2}", &mut state), "3");
+    assert_eq!(say("{say 1 / 0}", &mut state), "inf");
+}
+
+#[test]
+fn test_interpreter_seq() {
+    let mut state = State::default();
+    let dialog = "{sequence\n  - one\n  - two\n  - three\n}";
+    let words: Vec<_> = (0..5).map(|_| say(dialog, &mut state)).collect();
+    assert_eq!(words, vec!["one", "two", "three", "three", "three"]);
+
+    let mut state = State::default();
+    let dialog = "{cycle\n  - one\n  - two\n}";
+    let words: Vec<_> = (0..5).map(|_| say(dialog, &mut state)).collect();
+    assert_eq!(words, vec!["one", "two", "one", "two", "one"]);
+
+    // Counters are scoped by the dialog ID.
+    let mut state = State::default();
+    assert_eq!(say(dialog, &mut state), "one");
+    state.dialog = "other".to_string();
+    assert_eq!(say(dialog, &mut state), "one");
+    state.dialog = "".to_string();
+    assert_eq!(say(dialog, &mut state), "two");
+
+    // Nested blocks have their own counters.
+    let mut state = State::default();
+    let dialog = "{cycle\n  - {sequence\n    - a\n    - b\n  }\n  - c\n}";
+    let words: Vec<_> = (0..5).map(|_| say(dialog, &mut state)).collect();
+    assert_eq!(words, vec!["a", "c", "b", "c", "b"]);
+}
+
+#[test]
+fn test_interpreter_shuffle() {
+    let mut state = State::default();
+    let dialog = "{shuffle\n  - a\n  - b\n  - c\n}";
+    for _ in 0..4 {
+        let mut words: Vec<_> = (0..3).map(|_| say(dialog, &mut state)).collect();
+        words.sort();
+        assert_eq!(words, vec!["a", "b", "c"]);
+    }
+}
+
+#[test]
+fn test_interpreter_seq_words() {
+    let mut state = State::default();
+    let dialog = "hi {sequence\n  - oh{br}hi\n  - bye\n} mark";
+    let tokenizer = Tokenizer::new(dialog);
+    let words: Vec<_> = Interpreter::new(tokenizer, &mut state).collect();
+    let exp = vec![
+        Word::Text("hi ".to_string(), Effects::default()),
+        Word::Text("oh".to_string(), Effects::default()),
+        Word::LineBreak,
+        Word::Text("hi".to_string(), Effects::default()),
+        Word::Text(" mark".to_string(), Effects::default()),
+    ];
+    assert_eq!(words, exp);
+}
+
+#[test]
+fn test_interpreter_if() {
+    let dialog = r#"{
+  - {item "key"} >= 2 ?
+    two
+  - {item "key"} == 1 ?
+    one{
+      - a == 3 ? three
+      - else ? nope
+    }
+  - else ?
+    none
+}"#;
+    let mut state = State::default();
+    assert_eq!(say(dialog, &mut state), "none");
+    state.inventory.put("key".to_string());
+    assert_eq!(say(dialog, &mut state), "onenope");
+    state.vars.set("a".to_string(), Val::I(3));
+    assert_eq!(say(dialog, &mut state), "onethree");
+    state.inventory.put("key".to_string());
+    assert_eq!(say(dialog, &mut state), "two");
+
+    // No branch matched.
+    let mut state = State::default();
+    assert_eq!(say("{- a ? yes}", &mut state), "");
+    state.vars.set("a".to_string(), Val::I(1));
+    assert_eq!(say("{- a ? yes}", &mut state), "yes");
+
+    // Like in Bitsy, only values equal to 1 are true.
+    state.vars.set("a".to_string(), Val::I(2));
+    assert_eq!(say("{- a ? yes}", &mut state), "");
+    state.vars.set("a".to_string(), Val::S("0".to_string()));
+    assert_eq!(say("{- a ? yes}", &mut state), "");
+}
+
+#[test]
+fn test_interpreter_item() {
+    let mut state = State::default();
+    assert_eq!(say(r#"{item "key" 3}{say {item "key"}}"#, &mut state), "3");
+    assert_eq!(say(r#"{give "key"}{give "key" 2}"#, &mut state), "");
+    assert_eq!(state.inventory.get("key"), 6);
+    assert_eq!(say(r#"{take "key"}{take "key" 10}"#, &mut state), "");
+    assert!(!state.inventory.contains("key"));
+
+    // The count is an expression.
+    state.vars.set("n".to_string(), Val::I(4));
+    say(
+        r#"{item "key" n * 2}{item "key" {item "key"} - 1}"#,
+        &mut state,
+    );
+    assert_eq!(state.inventory.get("key"), 7);
+    say(
+        r#"{item "key" -3}{give "tea" 1.5}{give "cat" "2"}"#,
+        &mut state,
+    );
+    assert_eq!(state.inventory.get("key"), 0);
+    assert_eq!(state.inventory.get("tea"), 1);
+    assert_eq!(state.inventory.get("cat"), 2);
+
+    // Reading the count doesn't change the inventory.
+    say(r#"{item "cat"}"#, &mut state);
+    assert_eq!(state.inventory.get("cat"), 2);
+}
+
 fn run(t: &str) -> (Vec<Word>, State) {
     let mut state = State::default();
     let tokenizer = Tokenizer::new(t);
-    let interpreter = Interpreter {
-        tokens: tokenizer,
-        state: &mut state,
-    };
+    let interpreter = Interpreter::new(tokenizer, &mut state);
     let words: Vec<_> = interpreter.collect();
     (words, state)
 }
+
+fn text(t: &str, effects: Effects) -> Word {
+    Word::Text(t.to_string(), effects)
+}
+
+/// Interpret the dialog and concatenate all text it produces.
+fn say(t: &str, state: &mut State) -> String {
+    let tokenizer = Tokenizer::new(t);
+    let mut res = String::new();
+    for word in Interpreter::new(tokenizer, state) {
+        if let Word::Text(text, _) = word {
+            res.push_str(&text);
+        }
+    }
+    res
+}
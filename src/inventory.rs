@@ -1,9 +1,24 @@
 use crate::*;
+use alloc::vec::Vec;
 use hashbrown::HashMap;
 
+/// Items held by the player and how many of each.
+///
+/// All operations saturate: counts never go below 0 or above `u16::MAX`.
+/// Items which count drops to 0 are not held anymore.
 #[derive(Default, Clone, Debug)]
 pub struct Inventory {
     items: HashMap<ID, u16>,
+    /// Changes since the last call to `take_changes`.
+    changes: Vec<Change>,
+}
+
+/// A change of the item count in the inventory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub id: ID,
+    pub old: u16,
+    pub new: u16,
 }
 
 impl Inventory {
@@ -11,23 +26,93 @@ impl Inventory {
         Self::default()
     }
 
+    /// Add one item. Returns the new count.
     pub fn put(&mut self, id: ID) -> u16 {
-        *self
-            .items
-            .entry(id)
-            .and_modify(|q| *q = q.saturating_add(1))
-            .or_insert(1)
+        self.add(id, 1)
     }
 
+    /// Remove one item. Returns the new count.
     pub fn pop(&mut self, id: ID) -> u16 {
-        *self
-            .items
-            .entry(id)
-            .and_modify(|q| *q = q.saturating_sub(1))
-            .or_insert(1)
+        self.remove(id, 1)
+    }
+
+    /// Add the given number of items. Returns the new count.
+    pub fn add(&mut self, id: ID, n: u16) -> u16 {
+        let count = self.get(&id).saturating_add(n);
+        self.set(id, count);
+        count
+    }
+
+    /// Remove the given number of items. Returns the new count.
+    pub fn remove(&mut self, id: ID, n: u16) -> u16 {
+        let count = self.get(&id).saturating_sub(n);
+        self.set(id, count);
+        count
     }
 
-    pub fn get(&self, id: &ID) -> u16 {
+    /// Set how many items of the given kind the player has.
+    pub fn set(&mut self, id: ID, count: u16) {
+        let old = self.get(&id);
+        if old == count {
+            return;
+        }
+        if count == 0 {
+            self.items.remove(&id);
+        } else {
+            self.items.insert(id.clone(), count);
+        }
+        self.record(id, old, count);
+    }
+
+    /// How many items of the given kind the player has.
+    pub fn get(&self, id: &str) -> u16 {
         self.items.get(id).copied().unwrap_or_default()
     }
+
+    /// Check if the player has at least one item of the given kind.
+    pub fn contains(&self, id: &str) -> bool {
+        self.items.contains_key(id)
+    }
+
+    /// Iterate over held items and their counts, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&ID, u16)> {
+        self.items.iter().map(|(id, count)| (id, *count))
+    }
+
+    /// The number of different items held.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Check if the player holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Remove all items.
+    pub fn clear(&mut self) {
+        for (id, old) in core::mem::take(&mut self.items) {
+            self.record(id, old, 0);
+        }
+    }
+
+    /// Get all changes since the last call, one per changed item.
+    ///
+    /// Use it to update the inventory UI only when something changed.
+    pub fn take_changes(&mut self) -> Vec<Change> {
+        core::mem::take(&mut self.changes)
+    }
+
+    /// Record the change, merging it with the pending change of the same item.
+    fn record(&mut self, id: ID, old: u16, new: u16) {
+        let Some(index) = self.changes.iter().position(|c| c.id == id) else {
+            self.changes.push(Change { id, old, new });
+            return;
+        };
+        let change = &mut self.changes[index];
+        change.new = new;
+        if change.old == change.new {
+            self.changes.remove(index);
+        }
+    }
 }
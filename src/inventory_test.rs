@@ -0,0 +1,85 @@
+use crate::*;
+
+fn id(s: &str) -> ID {
+    s.to_string()
+}
+
+#[test]
+fn test_inventory_put_pop() {
+    let mut inv = Inventory::new();
+    assert_eq!(inv.put(id("key")), 1);
+    assert_eq!(inv.put(id("key")), 2);
+    assert_eq!(inv.pop(id("key")), 1);
+    assert_eq!(inv.pop(id("key")), 0);
+    assert!(!inv.contains("key"));
+
+    // Popping a missing item doesn't add it.
+    assert_eq!(inv.pop(id("tea")), 0);
+    assert_eq!(inv.get("tea"), 0);
+    assert!(inv.is_empty());
+}
+
+#[test]
+fn test_inventory_counts() {
+    let mut inv = Inventory::new();
+    assert_eq!(inv.add(id("coin"), 10), 10);
+    assert_eq!(inv.remove(id("coin"), 3), 7);
+    assert_eq!(inv.remove(id("coin"), 30), 0);
+    assert!(!inv.contains("coin"));
+
+    inv.set(id("coin"), u16::MAX - 1);
+    assert_eq!(inv.add(id("coin"), 5), u16::MAX);
+    assert_eq!(inv.put(id("coin")), u16::MAX);
+    inv.set(id("coin"), 0);
+    assert_eq!(inv.len(), 0);
+}
+
+#[test]
+fn test_inventory_iter() {
+    let mut inv = Inventory::new();
+    inv.add(id("a"), 2);
+    inv.put(id("b"));
+    inv.put(id("c"));
+    inv.pop(id("c"));
+    let mut items: Vec<_> = inv.iter().collect();
+    items.sort();
+    assert_eq!(items, vec![(&id("a"), 2), (&id("b"), 1)]);
+    assert_eq!(inv.len(), 2);
+
+    inv.clear();
+    assert!(inv.is_empty());
+    assert_eq!(inv.iter().count(), 0);
+}
+
+#[test]
+fn test_inventory_changes() {
+    let change = |i: &str, old, new| Change {
+        id: id(i),
+        old,
+        new,
+    };
+    let mut inv = Inventory::new();
+    assert!(inv.take_changes().is_empty());
+
+    inv.put(id("a"));
+    inv.put(id("a"));
+    inv.add(id("b"), 3);
+    inv.pop(id("missing"));
+    assert_eq!(
+        inv.take_changes(),
+        vec![change("a", 0, 2), change("b", 0, 3)]
+    );
+    assert!(inv.take_changes().is_empty());
+
+    // Changes that cancel each other out are not reported.
+    inv.put(id("a"));
+    inv.pop(id("a"));
+    inv.set(id("b"), 3);
+    assert!(inv.take_changes().is_empty());
+
+    inv.remove(id("b"), 1);
+    inv.clear();
+    let mut changes = inv.take_changes();
+    changes.sort_by(|a, b| a.id.cmp(&b.id));
+    assert_eq!(changes, vec![change("a", 2, 0), change("b", 3, 0)]);
+}
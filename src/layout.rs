@@ -0,0 +1,250 @@
+use crate::*;
+use alloc::vec::Vec;
+use core::mem;
+
+/// Width of a dialog line in font pixels, as in Bitsy.
+pub const ROW_WIDTH: u16 = 192;
+
+/// How many lines fit into the dialog box, as in Bitsy.
+pub const ROWS_PER_PAGE: usize = 2;
+
+/// Width of an inline drawing in font pixels.
+///
+/// Text in Bitsy is drawn at half the scale of the room,
+/// so an 8x8 drawing is as wide as 16 pixels of text.
+pub const DRAWING_WIDTH: u16 = 16;
+
+/// Something that knows how wide each character is.
+///
+/// Implemented for any `Fn(char) -> u16`.
+pub trait CharWidth {
+    /// The width of the character in font pixels, including spacing.
+    fn char_width(&self, ch: char) -> u16;
+}
+
+impl<F: Fn(char) -> u16> CharWidth for F {
+    fn char_width(&self, ch: char) -> u16 {
+        self(ch)
+    }
+}
+
+/// What is shown in a dialog line at a given position.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GlyphKind {
+    Char(char),
+    Sprite(ID),
+    Tile(ID),
+    Item(ID),
+}
+
+/// A single character or drawing placed on a dialog line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glyph {
+    pub kind: GlyphKind,
+    pub effects: Effects,
+    /// The offset from the line start in font pixels.
+    pub x: u16,
+    pub width: u16,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Line {
+    pub glyphs: Vec<Glyph>,
+    /// The width of all glyphs on the line in font pixels.
+    pub width: u16,
+}
+
+/// A single screen of the dialog box.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Page {
+    pub lines: Vec<Line>,
+    /// Dialog events with the number of glyphs on the page that go before them.
+    pub events: Vec<(usize, DialogEvent)>,
+}
+
+impl Page {
+    /// The number of glyphs on all lines of the page.
+    pub fn len(&self) -> usize {
+        self.lines.iter().map(|line| line.glyphs.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(|line| line.glyphs.is_empty())
+    }
+}
+
+/// Wraps words into dialog lines and pages.
+pub struct Layout<W: CharWidth> {
+    pub char_width: W,
+    /// The maximum line width in font pixels.
+    pub width: u16,
+    /// The maximum number of lines on a page.
+    pub rows: usize,
+    pub drawing_width: u16,
+}
+
+impl<W: CharWidth> Layout<W> {
+    /// Layout with the dialog box size used by Bitsy.
+    pub fn new(char_width: W) -> Self {
+        Self {
+            char_width,
+            width: ROW_WIDTH,
+            rows: ROWS_PER_PAGE,
+            drawing_width: DRAWING_WIDTH,
+        }
+    }
+
+    /// Split the words into pages.
+    ///
+    /// Lines are wrapped at spaces. Words that don't fit into a line
+    /// on their own are broken at any character. Events are kept on the page
+    /// where the text before them ends. Events after the last glyph of the dialog
+    /// go to the last page, which is empty if the dialog has no text at all.
+    pub fn pages<I: IntoIterator<Item = Word>>(&self, words: I) -> Vec<Page> {
+        let mut b = Builder {
+            layout: self,
+            pages: Vec::new(),
+            page: Page::default(),
+            line: Line::default(),
+            spaces: Vec::new(),
+            word: Vec::new(),
+        };
+        for word in words {
+            match word {
+                Word::Text(text, effects) => {
+                    for ch in text.chars() {
+                        if ch.is_whitespace() {
+                            b.flush_word();
+                            b.spaces.push(b.glyph(GlyphKind::Char(' '), effects));
+                        } else {
+                            b.word.push(b.glyph(GlyphKind::Char(ch), effects));
+                        }
+                    }
+                }
+                Word::Sprite(id) => b.push_drawing(GlyphKind::Sprite(id)),
+                Word::Tile(id) => b.push_drawing(GlyphKind::Tile(id)),
+                Word::Item(id) => b.push_drawing(GlyphKind::Item(id)),
+                Word::LineBreak => {
+                    b.flush_word();
+                    b.spaces.clear();
+                    b.new_line();
+                }
+                Word::PageBreak => {
+                    b.flush_word();
+                    b.spaces.clear();
+                    b.new_page();
+                }
+                Word::Event(event) => {
+                    b.flush_word();
+                    let index = b.page.len() + b.line.glyphs.len();
+                    b.page.events.push((index, event));
+                }
+            }
+        }
+        b.flush_word();
+        b.new_page();
+        if !b.page.events.is_empty() {
+            match b.pages.last_mut() {
+                Some(last) => {
+                    let index = last.len();
+                    let events = b.page.events.drain(..);
+                    last.events.extend(events.map(|(_, event)| (index, event)));
+                }
+                None => b.pages.push(mem::take(&mut b.page)),
+            }
+        }
+        b.pages
+    }
+}
+
+/// The state of splitting words into pages.
+struct Builder<'a, W: CharWidth> {
+    layout: &'a Layout<W>,
+    pages: Vec<Page>,
+    page: Page,
+    line: Line,
+    /// Spaces that go before the current word.
+    spaces: Vec<Glyph>,
+    /// Glyphs of the word that is not placed on the line yet.
+    word: Vec<Glyph>,
+}
+
+impl<W: CharWidth> Builder<'_, W> {
+    fn glyph(&self, kind: GlyphKind, effects: Effects) -> Glyph {
+        let width = match kind {
+            GlyphKind::Char(ch) => self.layout.char_width.char_width(ch),
+            _ => self.layout.drawing_width,
+        };
+        Glyph {
+            kind,
+            effects,
+            x: 0,
+            width,
+        }
+    }
+
+    /// Drawings are a part of the word they are attached to.
+    fn push_drawing(&mut self, kind: GlyphKind) {
+        let glyph = self.glyph(kind, Effects::default());
+        self.word.push(glyph);
+    }
+
+    /// Place the current word on the line, wrapping it if it doesn't fit.
+    fn flush_word(&mut self) {
+        if self.word.is_empty() {
+            return;
+        }
+        let spaces = mem::take(&mut self.spaces);
+        let word = mem::take(&mut self.word);
+        let spaces_width = total_width(&spaces);
+        let word_width = total_width(&word);
+        let width = self
+            .line
+            .width
+            .saturating_add(spaces_width)
+            .saturating_add(word_width);
+        if width > self.layout.width && !self.line.glyphs.is_empty() {
+            self.new_line();
+        } else {
+            for glyph in spaces {
+                self.place(glyph);
+            }
+        }
+        for glyph in word {
+            let width = self.line.width.saturating_add(glyph.width);
+            if width > self.layout.width && !self.line.glyphs.is_empty() {
+                self.new_line();
+            }
+            self.place(glyph);
+        }
+    }
+
+    fn place(&mut self, mut glyph: Glyph) {
+        glyph.x = self.line.width;
+        self.line.width = self.line.width.saturating_add(glyph.width);
+        self.line.glyphs.push(glyph);
+    }
+
+    fn new_line(&mut self) {
+        let line = mem::take(&mut self.line);
+        self.page.lines.push(line);
+        if self.page.lines.len() >= self.layout.rows {
+            self.pages.push(mem::take(&mut self.page));
+        }
+    }
+
+    /// Finish the current page. Events of a page without lines go to the next one.
+    fn new_page(&mut self) {
+        if !self.line.glyphs.is_empty() {
+            self.new_line();
+        }
+        if !self.page.lines.is_empty() {
+            self.pages.push(mem::take(&mut self.page));
+        }
+    }
+}
+
+/// The width of all glyphs. Saturates instead of overflowing on very long words.
+fn total_width(glyphs: &[Glyph]) -> u16 {
+    glyphs.iter().fold(0, |w, g| w.saturating_add(g.width))
+}
@@ -0,0 +1,98 @@
+use crate::*;
+
+/// Lay out the dialog and render each page as lines of text.
+fn pages(t: &str, width: u16) -> Vec<Vec<String>> {
+    let mut state = State::default();
+    let words = Interpreter::new(Tokenizer::new(t), &mut state);
+    let mut layout = Layout::new(|_| 1);
+    layout.width = width;
+    let mut res = Vec::new();
+    for page in layout.pages(words) {
+        let mut lines = Vec::new();
+        for line in page.lines {
+            let mut s = String::new();
+            for glyph in line.glyphs {
+                match glyph.kind {
+                    GlyphKind::Char(ch) => s.push(ch),
+                    _ => s.push('#'),
+                }
+            }
+            lines.push(s);
+        }
+        res.push(lines);
+    }
+    res
+}
+
+#[test]
+fn test_layout_wrap() {
+    assert_eq!(pages("hello world", 20), vec![vec!["hello world"]]);
+    assert_eq!(pages("hello world", 8), vec![vec!["hello", "world"]]);
+    assert_eq!(
+        pages("aa bb cc dd ee", 5),
+        vec![vec!["aa bb", "cc dd"], vec!["ee"]]
+    );
+    assert_eq!(pages("abcdefgh", 3), vec![vec!["abc", "def"], vec!["gh"]]);
+    assert_eq!(pages("a{br}b{br}c", 20), vec![vec!["a", "b"], vec!["c"]]);
+    assert_eq!(pages("a{br}{br}b", 20), vec![vec!["a", ""], vec!["b"]]);
+    assert_eq!(pages("a{pg}b c", 20), vec![vec!["a"], vec!["b c"]]);
+    assert_eq!(pages("a{br}b{pg}c", 20), vec![vec!["a", "b"], vec!["c"]]);
+    assert_eq!(pages("", 20), Vec::<Vec<&str>>::new());
+    assert_eq!(pages("{end}", 20), vec![Vec::<&str>::new()]);
+    assert_eq!(pages("a{pal \"1\"}b", 20), vec![vec!["ab"]]);
+    assert_eq!(pages("hi {wvy}you{/wvy}!", 20), vec![vec!["hi you!"]]);
+    assert_eq!(pages("hi {wvy}you{/wvy}!", 5), vec![vec!["hi", "you!"]]);
+    assert_eq!(
+        pages("a {drwt \"t\"}{drws \"s\"} b", 35),
+        vec![vec!["a ##", "b"]]
+    );
+}
+
+#[test]
+fn test_layout_glyphs() {
+    let mut state = State::default();
+    let words = Interpreter::new(Tokenizer::new("ab {clr1}c{/clr1}{drwi \"i\"}"), &mut state);
+    let layout = Layout::new(|ch| if ch == ' ' { 2 } else { 6 });
+    let pages = layout.pages(words);
+    assert_eq!(pages.len(), 1);
+    let line = &pages[0].lines[0];
+    assert_eq!(line.width, 6 + 6 + 2 + 6 + DRAWING_WIDTH);
+    let xs: Vec<_> = line.glyphs.iter().map(|g| g.x).collect();
+    assert_eq!(xs, vec![0, 6, 12, 14, 20]);
+    let color = Effects::default().with(TextEffect::Color(1));
+    assert_eq!(line.glyphs[3].kind, GlyphKind::Char('c'));
+    assert_eq!(line.glyphs[3].effects, color);
+    assert_eq!(line.glyphs[4].kind, GlyphKind::Item("i".to_string()));
+    assert_eq!(line.glyphs[4].width, DRAWING_WIDTH);
+}
+
+#[test]
+fn test_layout_events() {
+    let mut state = State::default();
+    let text = "{ava \"a\"}hi you{pal \"1\"} there{pg}{end}";
+    let words = Interpreter::new(Tokenizer::new(text), &mut state);
+    let mut layout = Layout::new(|_| 1);
+    layout.width = 6;
+    layout.rows = 1;
+    let pages = layout.pages(words);
+    assert_eq!(pages.len(), 2);
+    let ava = DialogEvent::Ava("a".to_string());
+    let pal = DialogEvent::Pal("1".to_string());
+    assert_eq!(pages[0].events, vec![(0, ava), (6, pal)]);
+    // Events after the last page break go to the last page.
+    assert_eq!(pages[1].events, vec![(5, DialogEvent::End)]);
+}
+
+#[test]
+fn test_layout_long_word() {
+    let words = || vec![Word::Text("a".repeat(12_000), Effects::default())];
+    let pages = Layout::new(|_| 6).pages(words());
+    assert_eq!(pages.len(), (12_000 / 32usize).div_ceil(ROWS_PER_PAGE));
+    assert!(pages.iter().all(|p| p.lines.iter().all(|l| l.width == 192)));
+
+    // The line is wider than can be measured.
+    let mut layout = Layout::new(|_| 6);
+    layout.width = u16::MAX;
+    let pages = layout.pages(words());
+    assert_eq!(pages[0].lines[0].width, u16::MAX);
+}
@@ -1,18 +1,73 @@
 #![cfg_attr(not(test), no_std)]
 extern crate alloc;
 
+#[doc = include_str!("../README.md")]
+#[cfg(doctest)]
+pub struct ReadmeDoctests;
+
+mod diagnostic;
+mod effects;
+#[cfg(test)]
+mod effects_test;
+mod font;
+#[cfg(test)]
+mod font_test;
+mod functions;
+mod game;
+#[cfg(test)]
+mod game_test;
+mod html;
+#[cfg(test)]
+mod html_test;
 mod interpreter;
 #[cfg(test)]
 mod interpreter_test;
 mod inventory;
+#[cfg(test)]
+mod inventory_test;
+mod layout;
+#[cfg(test)]
+mod layout_test;
+mod parser;
+#[cfg(test)]
+mod parser_test;
+mod player;
+#[cfg(test)]
+mod player_test;
+mod render;
+#[cfg(test)]
+mod render_test;
+mod span;
 mod state;
+mod synth;
+#[cfg(test)]
+mod synth_test;
 mod tokenizer;
 #[cfg(test)]
 mod tokenizer_test;
 mod vars;
+#[cfg(test)]
+mod vars_test;
+mod world;
+#[cfg(test)]
+mod world_test;
+mod writer;
 
+pub use diagnostic::*;
+pub use effects::*;
+pub use font::*;
+pub use functions::*;
+pub use game::*;
+pub use html::*;
 pub use interpreter::*;
 pub use inventory::*;
+pub use layout::*;
+pub use parser::*;
+pub use player::*;
+pub use render::*;
+pub use span::*;
 pub use state::*;
+pub use synth::*;
 pub use tokenizer::*;
 pub use vars::*;
+pub use world::*;
@@ -0,0 +1,152 @@
+use crate::*;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::discriminant;
+
+/// A node of the dialog tree.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    /// A single word of text.
+    Word(String),
+    /// Function call without a body, like {br}, {say}, or {exit}.
+    Func(Tag),
+    /// Nodes with a style effect applied.
+    Eff(TextEffect, Vec<Node>),
+    /// List block. Each item is a list of nodes.
+    Seq(u16, SeqKind, Vec<Vec<Node>>),
+    /// Conditional block. Each branch is a list of nodes.
+    If(Vec<(Option<Expr>, Vec<Node>)>),
+}
+
+/// Build a dialog tree from a flat stream of tokens.
+pub struct Parser<T: Iterator<Item = Token>> {
+    tokens: T,
+}
+
+impl<T: Iterator<Item = Token>> Parser<T> {
+    pub fn new(tokens: T) -> Self {
+        Self { tokens }
+    }
+
+    /// Parse all tokens into a list of top-level nodes.
+    pub fn parse(mut self) -> Vec<Node> {
+        parse_nodes(&mut self.tokens)
+    }
+}
+
+/// Parse the dialog text into a tree.
+pub fn parse(text: &str) -> Vec<Node> {
+    Parser::new(Tokenizer::new(text)).parse()
+}
+
+/// Convert the dialog tree back into a flat stream of tokens.
+///
+/// To interpret the tree, use [`Interpreter::from_nodes`] instead.
+pub fn flatten(nodes: Vec<Node>) -> vec::IntoIter<Token> {
+    let mut tokens = Vec::new();
+    flatten_into(nodes, &mut tokens);
+    tokens.into_iter()
+}
+
+/// Parse tokens into nodes.
+///
+/// A closing tag closes the innermost opened effect of the same kind,
+/// like in [`Interpreter`]. Effects opened after it are closed as well
+/// and opened again right after it, so `{wvy}{shk}a{/wvy}b{/shk}` is the same as
+/// `{wvy}{shk}a{/shk}{/wvy}{shk}b{/shk}`. Unmatched closing tags are ignored.
+fn parse_nodes<T>(tokens: &mut T) -> Vec<Node>
+where
+    T: Iterator<Item = Token>,
+{
+    let mut nodes = Vec::new();
+    // Opened effects with nodes inside of them, from outer to inner.
+    let mut open: Vec<(TextEffect, Vec<Node>)> = Vec::new();
+    for token in tokens {
+        let node = match token {
+            Token::Word(word) => Node::Word(word),
+            Token::OpenTag(Tag::Eff(eff)) => {
+                open.push((eff, Vec::new()));
+                continue;
+            }
+            Token::OpenTag(Tag::Seq(id, kind, items)) => {
+                let items = items.into_iter().map(parse_list).collect();
+                Node::Seq(id, kind, items)
+            }
+            Token::OpenTag(Tag::If(branches)) => {
+                let branches = branches
+                    .into_iter()
+                    .map(|(cond, tokens)| (cond, parse_list(tokens)))
+                    .collect();
+                Node::If(branches)
+            }
+            Token::OpenTag(tag) => Node::Func(tag),
+            Token::CloseTag(Tag::Eff(eff)) => {
+                let kind = discriminant(&eff);
+                if let Some(index) = open.iter().rposition(|(e, _)| discriminant(e) == kind) {
+                    let reopen: Vec<_> = open[index + 1..].iter().map(|(e, _)| *e).collect();
+                    close_effects(&mut open, &mut nodes, index);
+                    open.extend(reopen.into_iter().map(|e| (e, Vec::new())));
+                }
+                continue;
+            }
+            // Closing tags for functions without a body are meaningless.
+            Token::CloseTag(_) => continue,
+        };
+        match open.last_mut() {
+            Some((_, inner)) => inner.push(node),
+            None => nodes.push(node),
+        }
+    }
+    close_effects(&mut open, &mut nodes, 0);
+    nodes
+}
+
+/// Close all opened effects starting from the given index.
+fn close_effects(open: &mut Vec<(TextEffect, Vec<Node>)>, nodes: &mut Vec<Node>, index: usize) {
+    while open.len() > index {
+        let Some((eff, inner)) = open.pop() else {
+            break;
+        };
+        let node = Node::Eff(eff, inner);
+        match open.last_mut() {
+            Some((_, outer)) => outer.push(node),
+            None => nodes.push(node),
+        }
+    }
+}
+
+fn parse_list(tokens: Vec<Token>) -> Vec<Node> {
+    parse_nodes(&mut tokens.into_iter())
+}
+
+fn flatten_into(nodes: Vec<Node>, tokens: &mut Vec<Token>) {
+    for node in nodes {
+        match node {
+            Node::Word(word) => tokens.push(Token::Word(word)),
+            Node::Func(tag) => tokens.push(Token::OpenTag(tag)),
+            Node::Eff(eff, nodes) => {
+                tokens.push(Token::OpenTag(Tag::Eff(eff)));
+                flatten_into(nodes, tokens);
+                tokens.push(Token::CloseTag(Tag::Eff(eff)));
+            }
+            Node::Seq(id, kind, items) => {
+                let items = items.into_iter().map(flatten_list).collect();
+                tokens.push(Token::OpenTag(Tag::Seq(id, kind, items)));
+            }
+            Node::If(branches) => {
+                let branches = branches
+                    .into_iter()
+                    .map(|(cond, nodes)| (cond, flatten_list(nodes)))
+                    .collect();
+                tokens.push(Token::OpenTag(Tag::If(branches)));
+            }
+        }
+    }
+}
+
+fn flatten_list(nodes: Vec<Node>) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    flatten_into(nodes, &mut tokens);
+    tokens
+}
@@ -0,0 +1,95 @@
+use crate::*;
+
+#[test]
+fn test_parser() {
+    use Node::*;
+    check("hello world", vec![w("hello "), w("world")]);
+    check("oh{br}hi", vec![w("oh"), Func(Tag::Br), w("hi")]);
+    check(
+        "{wvy}oh {shk}hi{/shk}{/wvy}!",
+        vec![
+            Eff(
+                TextEffect::Wavy,
+                vec![w("oh "), Eff(TextEffect::Shaky, vec![w("hi")])],
+            ),
+            w("!"),
+        ],
+    );
+    check(
+        "{clr1}oh{/clr}hi",
+        vec![Eff(TextEffect::Color(1), vec![w("oh")]), w("hi")],
+    );
+    check("{wvy}oh", vec![Eff(TextEffect::Wavy, vec![w("oh")])]);
+    check("oh{/wvy}{/br}hi", vec![w("oh"), w("hi")]);
+    // Crossed tags close the innermost effect of the same kind.
+    check(
+        "{wvy}{shk}a{/wvy}b{/shk}",
+        vec![
+            Eff(TextEffect::Wavy, vec![Eff(TextEffect::Shaky, vec![w("a")])]),
+            Eff(TextEffect::Shaky, vec![w("b")]),
+        ],
+    );
+    check(
+        "{wvy}{shk}{wvy}a{/shk}b",
+        vec![Eff(
+            TextEffect::Wavy,
+            vec![
+                Eff(TextEffect::Shaky, vec![Eff(TextEffect::Wavy, vec![w("a")])]),
+                Eff(TextEffect::Wavy, vec![w("b")]),
+            ],
+        )],
+    );
+    check(
+        "{cycle\n - {rbw}a{/rbw}\n - b\n}",
+        vec![Seq(
+            0,
+            SeqKind::Cycle,
+            vec![vec![Eff(TextEffect::Rainbow, vec![w("a")])], vec![w("b")]],
+        )],
+    );
+    let cond = Expr::SimpleExpr(SimpleExpr::Var("a".to_string()));
+    check(
+        "{\n - a ? {wvy}yes{/wvy}\n - else ? no\n}",
+        vec![If(vec![
+            (Some(cond), vec![Eff(TextEffect::Wavy, vec![w("yes")])]),
+            (None, vec![w("no")]),
+        ])],
+    );
+}
+
+#[test]
+fn test_parser_flatten() {
+    let text = "{wvy}oh {shk}hi{/shk}{/wvy}{br}{cycle\n - {rbw}a{/rbw}\n - b\n}";
+    let tokens: Vec<_> = flatten(parse(text)).collect();
+    let expected: Vec<_> = Tokenizer::new(text).collect();
+    assert_eq!(tokens, expected);
+}
+
+#[test]
+fn test_parser_interpret() {
+    let texts = [
+        "{wvy}oh {shk}hi{/shk}{/wvy}!",
+        "{wvy}{shk}a{/wvy}b{/shk}c",
+        "{clr1}a{clr2}b{/clr}c{/clr}d",
+        "{rbw}a{/wvy}b{/rbw}c{/rbw}d",
+        "{wvy}{cycle\n - {shk}a{/shk}b\n - c\n}d{/wvy}",
+        "{sequence\n - a\n - b\n}{say \"{wvy}x\"}y",
+        "{cycle\n - {wvy}a\n - b\n}c",
+        "{if\n - true ? {shk}a{rbw}b\n - c\n}d{/shk}e",
+    ];
+    for text in texts {
+        let mut state = State::default();
+        let expected: Vec<_> = Interpreter::new(Tokenizer::new(text), &mut state).collect();
+        let mut state = State::default();
+        let words: Vec<_> = Interpreter::from_nodes(parse(text), &mut state).collect();
+        assert_eq!(words, expected, "{text}");
+    }
+}
+
+fn w(w: &str) -> Node {
+    Node::Word(w.to_string())
+}
+
+fn check(given: &str, expected: Vec<Node>) {
+    assert_eq!(parse(given), expected);
+}
@@ -0,0 +1,154 @@
+use crate::*;
+use alloc::vec::Vec;
+use core::mem;
+
+/// How long it takes to show a single character, in milliseconds, as in Bitsy.
+pub const CHAR_DURATION: u32 = 50;
+
+/// Shows the dialog character by character, page by page.
+///
+/// The whole dialog is interpreted and laid out when the player is created,
+/// so that [`DialogPlayer::has_next_page`] is known for every page. Because of that,
+/// the state is updated right away: variables, items, and the effects of tags
+/// like `{exit}` or `{end}` are already applied when `new` returns, even if
+/// the text before them is never shown. Dialog events, like exits or palette
+/// changes, are returned by [`DialogPlayer::update`] when the text before
+/// them is shown.
+pub struct DialogPlayer {
+    pages: Vec<Page>,
+    /// The index of the current page.
+    page: usize,
+    /// How many glyphs of the current page are shown.
+    shown: usize,
+    /// Milliseconds since the last character was shown.
+    timer: u32,
+    /// How many events of the current page are reached.
+    reached: usize,
+    /// Reached events that are not returned by `update` yet.
+    events: Vec<DialogEvent>,
+    /// How long it takes to show a single character, in milliseconds.
+    pub char_duration: u32,
+}
+
+impl DialogPlayer {
+    pub fn new<W, I>(layout: &Layout<W>, words: I) -> Self
+    where
+        W: CharWidth,
+        I: IntoIterator<Item = Word>,
+    {
+        Self {
+            pages: layout.pages(words),
+            page: 0,
+            shown: 0,
+            timer: 0,
+            reached: 0,
+            events: Vec::new(),
+            char_duration: CHAR_DURATION,
+        }
+    }
+
+    /// Advance the time by the given number of milliseconds, showing more characters.
+    ///
+    /// Returns dialog events reached since the last call, including the ones
+    /// reached by `skip` and `r#continue`.
+    pub fn update(&mut self, dt: u32) -> Vec<DialogEvent> {
+        if !self.is_page_complete() {
+            self.timer = self.timer.saturating_add(dt);
+            let total = self.page_len();
+            while self.timer >= self.char_duration && self.shown < total {
+                self.timer -= self.char_duration;
+                self.shown += 1;
+            }
+        }
+        self.reach();
+        mem::take(&mut self.events)
+    }
+
+    /// Show all characters of the current page.
+    pub fn skip(&mut self) {
+        self.shown = self.page_len();
+        self.timer = 0;
+        self.reach();
+    }
+
+    /// Handle the player pressing a button.
+    ///
+    /// Shows the rest of the page if it's not complete yet
+    /// or goes to the next page otherwise.
+    /// Returns false if the dialog is over.
+    pub fn r#continue(&mut self) -> bool {
+        if self.is_finished() {
+            return false;
+        }
+        if !self.is_page_complete() {
+            self.skip();
+            return true;
+        }
+        self.page += 1;
+        self.shown = 0;
+        self.timer = 0;
+        self.reached = 0;
+        !self.is_finished()
+    }
+
+    /// Check if all characters of the current page are shown.
+    pub fn is_page_complete(&self) -> bool {
+        self.shown >= self.page_len()
+    }
+
+    /// Check if all pages were shown and the dialog can be closed.
+    pub fn is_finished(&self) -> bool {
+        self.page >= self.pages.len()
+    }
+
+    /// Check if there are more pages after the current one.
+    ///
+    /// If the page is complete, hosts should show the next page arrow.
+    pub fn has_next_page(&self) -> bool {
+        self.page + 1 < self.pages.len()
+    }
+
+    /// The part of the current page that should be shown.
+    pub fn page(&self) -> Page {
+        let Some(page) = self.pages.get(self.page) else {
+            return Page::default();
+        };
+        let mut left = self.shown;
+        let mut lines = Vec::new();
+        for line in &page.lines {
+            if left == 0 {
+                break;
+            }
+            let glyphs: Vec<_> = line.glyphs.iter().take(left).cloned().collect();
+            left -= glyphs.len();
+            let width = glyphs.last().map(|g| g.x + g.width).unwrap_or_default();
+            lines.push(Line { glyphs, width });
+        }
+        let events = page.events[..self.reached].to_vec();
+        Page { lines, events }
+    }
+
+    /// All pages of the dialog.
+    pub fn pages(&self) -> &[Page] {
+        &self.pages
+    }
+
+    /// The number of glyphs on the current page.
+    fn page_len(&self) -> usize {
+        self.pages.get(self.page).map(Page::len).unwrap_or_default()
+    }
+
+    /// Queue events of the current page which glyphs before them are shown.
+    fn reach(&mut self) {
+        let Some(page) = self.pages.get(self.page) else {
+            return;
+        };
+        for (index, event) in &page.events[self.reached..] {
+            if *index > self.shown {
+                break;
+            }
+            self.events.push(event.clone());
+            self.reached += 1;
+        }
+    }
+}
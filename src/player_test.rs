@@ -0,0 +1,139 @@
+use crate::*;
+
+fn player(text: &str) -> DialogPlayer {
+    let mut state = State::default();
+    let words = Interpreter::new(Tokenizer::new(text), &mut state);
+    let mut layout = Layout::new(|_| 1);
+    layout.width = 5;
+    DialogPlayer::new(&layout, words)
+}
+
+/// The text shown on the current page, line by line.
+fn shown(player: &DialogPlayer) -> Vec<String> {
+    let mut lines = Vec::new();
+    for line in player.page().lines {
+        let mut s = String::new();
+        for glyph in line.glyphs {
+            if let GlyphKind::Char(ch) = glyph.kind {
+                s.push(ch);
+            }
+        }
+        lines.push(s);
+    }
+    lines
+}
+
+#[test]
+fn test_player_update() {
+    let mut p = player("hi you");
+    assert_eq!(p.pages().len(), 1);
+    assert!(shown(&p).is_empty());
+    assert!(!p.is_page_complete());
+    p.update(49);
+    assert!(shown(&p).is_empty());
+    p.update(1);
+    assert_eq!(shown(&p), vec!["h"]);
+    p.update(80);
+    assert_eq!(shown(&p), vec!["hi"]);
+    p.update(40);
+    assert_eq!(shown(&p), vec!["hi", "y"]);
+    assert_eq!(p.page().lines[1].width, 1);
+    p.update(1000);
+    assert_eq!(shown(&p), vec!["hi", "you"]);
+    assert!(p.is_page_complete());
+    assert!(!p.has_next_page());
+    assert!(!p.r#continue());
+    assert!(p.is_finished());
+    assert!(shown(&p).is_empty());
+}
+
+#[test]
+fn test_player_pages() {
+    let mut p = player("one{pg}two three four");
+    assert_eq!(p.pages().len(), 3);
+    assert!(p.has_next_page());
+
+    // The first press shows the whole page, the second goes to the next one.
+    assert!(p.r#continue());
+    assert_eq!(shown(&p), vec!["one"]);
+    assert!(p.is_page_complete());
+    assert!(p.r#continue());
+    assert!(shown(&p).is_empty());
+    p.update(50);
+    assert_eq!(shown(&p), vec!["t"]);
+
+    p.skip();
+    assert_eq!(shown(&p), vec!["two", "three"]);
+    assert!(p.has_next_page());
+    p.update(1000);
+    assert_eq!(shown(&p), vec!["two", "three"]);
+    assert!(p.r#continue());
+    p.skip();
+    assert_eq!(shown(&p), vec!["four"]);
+    assert!(!p.has_next_page());
+    assert!(!p.r#continue());
+    assert!(p.is_finished());
+}
+
+#[test]
+fn test_player_speed() {
+    let mut p = player("abc");
+    p.char_duration = 0;
+    p.update(0);
+    assert_eq!(shown(&p), vec!["abc"]);
+
+    let mut p = player("");
+    assert!(p.is_finished());
+    assert!(p.is_page_complete());
+    assert!(!p.r#continue());
+}
+
+#[test]
+fn test_player_events() {
+    let pal = |id: &str| DialogEvent::Pal(id.to_string());
+    let mut p = player("{pal 1}ab{pal 2}{pg}c{end}");
+    assert_eq!(p.pages().len(), 2);
+    assert_eq!(p.pages()[0].events, vec![(0, pal("1")), (2, pal("2"))]);
+    assert_eq!(p.pages()[1].events, vec![(1, DialogEvent::End)]);
+
+    // Events are returned once the text before them is shown.
+    assert_eq!(p.update(0), vec![pal("1")]);
+    assert_eq!(p.update(50), vec![]);
+    assert_eq!(p.page().events, vec![(0, pal("1"))]);
+    assert_eq!(p.update(50), vec![pal("2")]);
+    assert_eq!(p.update(50), vec![]);
+    assert!(p.r#continue());
+    assert_eq!(p.update(0), vec![]);
+
+    // Events reached by skipping are returned by the next update.
+    p.skip();
+    assert_eq!(p.update(0), vec![DialogEvent::End]);
+    assert!(!p.r#continue());
+    assert_eq!(p.update(50), vec![]);
+
+    // A dialog without text still has a page for its events.
+    let mut p = player("{end}");
+    assert_eq!(p.pages().len(), 1);
+    assert!(p.is_page_complete());
+    assert_eq!(p.update(0), vec![DialogEvent::End]);
+    assert!(!p.r#continue());
+}
+
+#[test]
+fn test_player_state() {
+    // The dialog is interpreted when the player is created,
+    // before any of its pages are shown.
+    let mut state = State::default();
+    let text = "hi{pg}{a = 1}{give \"key\"}{end}bye";
+    let words = Interpreter::new(Tokenizer::new(text), &mut state);
+    let mut p = DialogPlayer::new(&Layout::new(|_| 1), words);
+    assert_eq!(state.vars.get("a"), &Val::I(1));
+    assert_eq!(state.inventory.get("key"), 1);
+    assert!(state.end);
+
+    // The event is still returned only when its page is shown.
+    p.skip();
+    assert_eq!(p.update(0), vec![]);
+    assert!(p.r#continue());
+    assert_eq!(p.update(0), vec![DialogEvent::End]);
+}
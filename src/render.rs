@@ -0,0 +1,219 @@
+use crate::*;
+use alloc::vec;
+use alloc::vec::Vec;
+use libm::floorf;
+
+/// Dialog box width in font pixels.
+pub const BOX_WIDTH: u16 = 208;
+
+/// Dialog box height in font pixels.
+pub const BOX_HEIGHT: u16 = 38;
+
+/// Horizontal padding between the dialog box edge and the text.
+const PADDING_X: u16 = 8;
+
+/// Vertical padding between the dialog box edge and the first line.
+const PADDING_Y: u16 = 4;
+
+/// Distance between the tops of two consecutive lines.
+const LINE_HEIGHT: u16 = 12;
+
+/// The arrow shown when there are more pages, as in Bitsy.
+const ARROW: [[bool; 5]; 3] = [
+    [true, true, true, true, true],
+    [false, true, true, true, false],
+    [false, false, true, false, false],
+];
+
+/// An RGBA color.
+pub type Color = [u8; 4];
+
+/// Dialog box background, as in Bitsy.
+pub const BACKGROUND: Color = [0, 0, 0, 255];
+
+/// Default text color, as in Bitsy.
+pub const FOREGROUND: Color = [255, 255, 255, 255];
+
+/// A buffer of RGBA pixels, row by row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Canvas {
+    pub width: u16,
+    pub height: u16,
+    /// 4 bytes per pixel: red, green, blue, alpha.
+    pub pixels: Vec<u8>,
+}
+
+impl Canvas {
+    /// A canvas filled with the given color.
+    pub fn new(width: u16, height: u16, color: Color) -> Self {
+        let size = usize::from(width) * usize::from(height);
+        let mut pixels = vec![0; size * 4];
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Get the color of the pixel. Returns `None` if outside the canvas.
+    pub fn get(&self, x: i32, y: i32) -> Option<Color> {
+        let i = self.index(x, y)?;
+        let mut color = [0; 4];
+        color.copy_from_slice(&self.pixels[i..i + 4]);
+        Some(color)
+    }
+
+    /// Set the color of the pixel. Pixels outside the canvas are ignored.
+    pub fn set(&mut self, x: i32, y: i32, color: Color) {
+        if let Some(i) = self.index(x, y) {
+            self.pixels[i..i + 4].copy_from_slice(&color);
+        }
+    }
+
+    /// Fill a square of the given size.
+    fn fill(&mut self, x: i32, y: i32, size: i32, color: Color) {
+        for dy in 0..size {
+            for dx in 0..size {
+                self.set(x + dx, y + dy, color);
+            }
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        let x = usize::try_from(x).ok()?;
+        let y = usize::try_from(y).ok()?;
+        let width = usize::from(self.width);
+        if x >= width || y >= usize::from(self.height) {
+            return None;
+        }
+        Some((y * width + x) * 4)
+    }
+}
+
+/// Draws dialog boxes.
+pub struct Renderer<'a> {
+    /// The game data to take drawings and palettes from.
+    pub world: &'a World,
+    pub font: &'a Font,
+    /// Time in milliseconds used to animate text effects.
+    pub time: u32,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(world: &'a World, font: &'a Font) -> Self {
+        Self {
+            world,
+            font,
+            time: 0,
+        }
+    }
+
+    /// Draw a page of the dialog in font pixels.
+    ///
+    /// Colors are taken from the current palette in the state.
+    /// If `arrow` is true, the arrow indicating the next page is shown.
+    pub fn dialog_box(&self, page: &Page, state: &State, arrow: bool) -> Canvas {
+        let mut canvas = Canvas::new(BOX_WIDTH, BOX_HEIGHT, BACKGROUND);
+        let palette = self.world.palette(&state.palette);
+        for (row, line) in page.lines.iter().enumerate() {
+            let y = PADDING_Y + LINE_HEIGHT * row as u16;
+            for (col, glyph) in line.glyphs.iter().enumerate() {
+                let (dx, dy) = glyph.effects.offset(self.time, col, row);
+                let x = i32::from(PADDING_X + glyph.x) + floorf(dx) as i32;
+                let y = i32::from(y) + floorf(dy) as i32;
+                self.draw_glyph(&mut canvas, glyph, col, x, y, palette);
+            }
+        }
+        if arrow {
+            draw_arrow(&mut canvas);
+        }
+        canvas
+    }
+
+    fn draw_glyph(
+        &self,
+        canvas: &mut Canvas,
+        glyph: &Glyph,
+        col: usize,
+        x: i32,
+        y: i32,
+        palette: Option<&Palette>,
+    ) {
+        let (frame, color) = match &glyph.kind {
+            GlyphKind::Char(ch) => {
+                let Some(ch) = self.font.get(*ch) else {
+                    return;
+                };
+                let color = match glyph.effects.color {
+                    Some(c) => palette_color(palette, c.saturating_sub(1)),
+                    None => FOREGROUND,
+                };
+                let color = match glyph.effects.rainbow(self.time, col) {
+                    Some([r, g, b]) => [r, g, b, 255],
+                    None => color,
+                };
+                let x = x + i32::from(ch.offset_x);
+                let y = y + i32::from(ch.offset_y);
+                draw_pixels(canvas, &ch.pixels, x, y, 1, color);
+                return;
+            }
+            GlyphKind::Tile(id) => {
+                let Some(tile) = self.world.tile(id) else {
+                    return;
+                };
+                (tile.frames.first(), tile.color.unwrap_or(1))
+            }
+            GlyphKind::Sprite(id) => {
+                let Some(sprite) = self.world.sprite(id) else {
+                    return;
+                };
+                (sprite.frames.first(), sprite.color.unwrap_or(2))
+            }
+            GlyphKind::Item(id) => {
+                let Some(item) = self.world.item(id) else {
+                    return;
+                };
+                (item.frames.first(), item.color.unwrap_or(2))
+            }
+        };
+        if let Some(frame) = frame {
+            let color = palette_color(palette, color);
+            draw_pixels(canvas, frame, x, y, 2, color);
+        }
+    }
+}
+
+/// Get a palette color by index. Missing colors are white.
+fn palette_color(palette: Option<&Palette>, index: u8) -> Color {
+    let rgb = palette.and_then(|p| p.colors.get(usize::from(index)));
+    match rgb {
+        Some([r, g, b]) => [*r, *g, *b, 255],
+        None => FOREGROUND,
+    }
+}
+
+/// Draw filled pixels of the bitmap, each pixel scaled to a square of the given size.
+fn draw_pixels<R>(canvas: &mut Canvas, pixels: &[R], x: i32, y: i32, scale: i32, color: Color)
+where
+    R: AsRef<[bool]>,
+{
+    for (dy, row) in pixels.iter().enumerate() {
+        for (dx, pixel) in row.as_ref().iter().enumerate() {
+            if *pixel {
+                let px = x + dx as i32 * scale;
+                let py = y + dy as i32 * scale;
+                canvas.fill(px, py, scale, color);
+            }
+        }
+    }
+}
+
+/// Draw the next page arrow in the bottom right corner.
+fn draw_arrow(canvas: &mut Canvas) {
+    let x = i32::from(BOX_WIDTH - PADDING_X) - 10;
+    let y = i32::from(BOX_HEIGHT) - 8;
+    draw_pixels(canvas, &ARROW, x, y, 2, FOREGROUND);
+}
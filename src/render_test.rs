@@ -0,0 +1,107 @@
+use crate::*;
+
+const FONT: &str = "FONT tiny
+SIZE 2 2
+CHAR 97
+10
+01
+CHAR 98
+SIZE 1 1
+OFFSET 1 1
+1
+";
+
+fn world() -> World {
+    World {
+        palettes: vec![Palette {
+            id: "0".to_string(),
+            colors: vec![[10, 10, 10], [20, 20, 20], [30, 30, 30]],
+            ..Palette::default()
+        }],
+        items: vec![Item {
+            id: "i".to_string(),
+            frames: vec![vec![vec![true, false], vec![false, true]]],
+            ..Item::default()
+        }],
+        ..World::default()
+    }
+}
+
+fn render(text: &str, arrow: bool) -> Canvas {
+    let world = world();
+    let font = Font::parse(FONT);
+    let mut state = State {
+        palette: "0".to_string(),
+        ..State::default()
+    };
+    let words: Vec<_> = Interpreter::new(Tokenizer::new(text), &mut state).collect();
+    let pages = Layout::new(&font).pages(words);
+    let renderer = Renderer::new(&world, &font);
+    renderer.dialog_box(&pages[0], &state, arrow)
+}
+
+#[test]
+fn test_render_canvas() {
+    let mut canvas = Canvas::new(2, 3, BACKGROUND);
+    assert_eq!(canvas.pixels.len(), 2 * 3 * 4);
+    assert_eq!(canvas.get(1, 2), Some(BACKGROUND));
+    canvas.set(1, 2, FOREGROUND);
+    assert_eq!(canvas.get(1, 2), Some(FOREGROUND));
+    assert_eq!(&canvas.pixels[20..], &FOREGROUND);
+    canvas.set(2, 0, FOREGROUND);
+    canvas.set(-1, 0, FOREGROUND);
+    assert_eq!(canvas.get(2, 0), None);
+    assert_eq!(canvas.get(0, -1), None);
+}
+
+#[test]
+fn test_render_text() {
+    let canvas = render("a{br}{clr2}a{/clr2}b", false);
+    assert_eq!((canvas.width, canvas.height), (BOX_WIDTH, BOX_HEIGHT));
+    assert_eq!(canvas.get(8, 4), Some(FOREGROUND));
+    assert_eq!(canvas.get(9, 4), Some(BACKGROUND));
+    assert_eq!(canvas.get(9, 5), Some(FOREGROUND));
+    // Second line, colored with the second palette color.
+    assert_eq!(canvas.get(8, 16), Some([20, 20, 20, 255]));
+    assert_eq!(canvas.get(9, 17), Some([20, 20, 20, 255]));
+    // The custom glyph with an offset.
+    assert_eq!(canvas.get(11, 17), Some(FOREGROUND));
+    assert_eq!(canvas.get(10, 16), Some(BACKGROUND));
+    // No arrow.
+    let filled = canvas.pixels.chunks(4).filter(|p| *p == FOREGROUND).count();
+    assert_eq!(filled, 3);
+}
+
+#[test]
+fn test_render_drawing() {
+    let canvas = render("{drwi \"i\"}", true);
+    let color = Some([30, 30, 30, 255]);
+    assert_eq!(canvas.get(8, 4), color);
+    assert_eq!(canvas.get(9, 5), color);
+    assert_eq!(canvas.get(10, 4), Some(BACKGROUND));
+    assert_eq!(canvas.get(10, 6), color);
+    assert_eq!(canvas.get(11, 7), color);
+    // The arrow.
+    assert_eq!(canvas.get(190, 30), Some(FOREGROUND));
+    assert_eq!(canvas.get(198, 30), Some(FOREGROUND));
+    assert_eq!(canvas.get(194, 34), Some(FOREGROUND));
+    assert_eq!(canvas.get(190, 34), Some(BACKGROUND));
+}
+
+#[test]
+fn test_render_effects() {
+    let world = world();
+    let font = Font::parse(FONT);
+    let mut state = State::default();
+    let words: Vec<_> =
+        Interpreter::new(Tokenizer::new("{wvy}a{/wvy}{rbw}a{/rbw}"), &mut state).collect();
+    let pages = Layout::new(&font).pages(words);
+    let mut renderer = Renderer::new(&world, &font);
+    renderer.time = 1178;
+    let canvas = renderer.dialog_box(&pages[0], &state, false);
+    // Moved up by 4 pixels.
+    assert_eq!(canvas.get(8, 0), Some(FOREGROUND));
+    assert_eq!(canvas.get(8, 4), Some(BACKGROUND));
+    let [r, g, b] = rainbow(1178, 1);
+    assert_eq!(canvas.get(10, 4), Some([r, g, b, 255]));
+}
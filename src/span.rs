@@ -0,0 +1,240 @@
+use crate::*;
+use alloc::vec::Vec;
+
+/// Position in the source text.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Pos {
+    /// Byte offset from the beginning of the text.
+    pub offset: usize,
+    /// Line number, starting from 1.
+    pub line: u32,
+    /// Column number in characters, starting from 1.
+    pub col: u32,
+}
+
+/// A range of the source text.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// Position of the first character.
+    pub start: Pos,
+    /// Position right after the last character.
+    pub end: Pos,
+}
+
+impl Span {
+    /// Get the part of the source text covered by the span.
+    pub fn source<'a>(&self, text: &'a str) -> &'a str {
+        &text[self.start.offset..self.end.offset]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    /// Position of the whole token, including curly braces of tags.
+    pub span: Span,
+    /// Position of the tag arguments.
+    ///
+    /// For assignments, it's the expression on the right side.
+    /// For words and tags without arguments, it's `None`.
+    pub args: Option<Span>,
+    /// Items of list blocks and branches of conditional blocks.
+    ///
+    /// Empty for all other tokens.
+    pub items: Vec<SpannedItem>,
+}
+
+/// An item of a list block or a branch of a conditional block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedItem {
+    /// Position of the item text, without the leading dash and the condition.
+    pub span: Span,
+    /// Position of the branch condition.
+    ///
+    /// For list items and "else" branches, it's `None`.
+    pub cond: Option<Span>,
+    /// Tokens of the item text.
+    pub tokens: Vec<SpannedToken>,
+}
+
+/// Tokenizer emitting tokens together with their position in the source.
+///
+/// Created by [`Tokenizer::spanned`].
+pub struct Spanned<'a> {
+    tokenizer: Tokenizer<'a>,
+    /// The last position for which line and column were calculated.
+    pos: Pos,
+}
+
+impl<'a> Spanned<'a> {
+    pub(crate) fn new(tokenizer: Tokenizer<'a>) -> Self {
+        let pos = Pos {
+            offset: 0,
+            line: 1,
+            col: 1,
+        };
+        let mut spanned = Self { tokenizer, pos };
+        spanned.pos = spanned.pos_at(spanned.tokenizer.offset());
+        spanned
+    }
+
+    /// Problems found in the text tokenized so far.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.tokenizer.diagnostics()
+    }
+
+    /// Calculate line and column for the given offset.
+    ///
+    /// Offsets must not decrease between calls, so that the whole text
+    /// is scanned only once.
+    fn pos_at(&mut self, offset: usize) -> Pos {
+        self.pos = advance(self.tokenizer.text(), self.pos, offset);
+        self.pos
+    }
+
+    /// Tokenize items of the block tag.
+    ///
+    /// Items are tokenized again with their own tokenizers, continuing
+    /// the block numbering from the given block ID.
+    fn items(&self, token: &Token, start: Pos, end: usize, blocks: u16) -> Vec<SpannedItem> {
+        let mut blocks = match token {
+            Token::OpenTag(Tag::Seq(id, ..)) => id.saturating_add(1),
+            Token::OpenTag(Tag::If(_)) => blocks,
+            _ => return Vec::new(),
+        };
+        let text = self.tokenizer.text();
+        let raw = &text[start.offset..end];
+        let span_of = |part: &str| {
+            let offset = part.as_ptr() as usize - text.as_ptr() as usize;
+            Span {
+                start: advance(text, start, offset),
+                end: advance(text, start, offset + part.len()),
+            }
+        };
+        let mut items = Vec::new();
+        for (cond, item) in find_items(raw) {
+            let span = span_of(item);
+            let mut tokenizer = Spanned::new(Tokenizer::nested(item, blocks));
+            let tokens = tokenizer
+                .by_ref()
+                .map(|token| token.shifted(span.start))
+                .collect();
+            blocks = tokenizer.tokenizer.blocks();
+            let cond = cond.map(span_of);
+            items.push(SpannedItem { span, cond, tokens });
+        }
+        items
+    }
+}
+
+impl SpannedToken {
+    /// Move the token from the beginning of the text to the given position.
+    fn shifted(mut self, base: Pos) -> Self {
+        self.span = self.span.shifted(base);
+        self.args = self.args.map(|args| args.shifted(base));
+        for item in &mut self.items {
+            item.span = item.span.shifted(base);
+            item.cond = item.cond.map(|cond| cond.shifted(base));
+            let tokens = core::mem::take(&mut item.tokens);
+            item.tokens = tokens.into_iter().map(|t| t.shifted(base)).collect();
+        }
+        self
+    }
+}
+
+impl Span {
+    fn shifted(self, base: Pos) -> Self {
+        Self {
+            start: self.start.shifted(base),
+            end: self.end.shifted(base),
+        }
+    }
+}
+
+impl Pos {
+    /// Convert the position relative to the base into the absolute one.
+    fn shifted(self, base: Pos) -> Self {
+        let col = if self.line == 1 {
+            base.col + self.col - 1
+        } else {
+            self.col
+        };
+        Self {
+            offset: base.offset + self.offset,
+            line: base.line + self.line - 1,
+            col,
+        }
+    }
+}
+
+impl Iterator for Spanned<'_> {
+    type Item = SpannedToken;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start_offset = self.tokenizer.offset();
+        let blocks = self.tokenizer.blocks();
+        let token = self.tokenizer.next()?;
+        let end_offset = self.tokenizer.offset();
+        let text = self.tokenizer.text();
+        let args = match token {
+            Token::Word(_) => None,
+            _ => find_args(text, start_offset, end_offset),
+        };
+        let start = self.pos_at(start_offset);
+        let args = args.map(|(args_start, args_end)| Span {
+            start: self.pos_at(args_start),
+            end: self.pos_at(args_end),
+        });
+        let items = self.items(&token, start, end_offset, blocks);
+        let end = self.pos_at(end_offset);
+        let span = Span { start, end };
+        Some(SpannedToken {
+            token,
+            span,
+            args,
+            items,
+        })
+    }
+}
+
+/// Calculate line and column for the given byte offset in the text.
+pub(crate) fn pos_at(text: &str, offset: usize) -> Pos {
+    let start = Pos {
+        offset: 0,
+        line: 1,
+        col: 1,
+    };
+    advance(text, start, offset)
+}
+
+/// Calculate line and column for the given byte offset after the known position.
+fn advance(text: &str, mut pos: Pos, offset: usize) -> Pos {
+    for ch in text[pos.offset..offset].chars() {
+        if ch == '\n' {
+            pos.line += 1;
+            pos.col = 1;
+        } else {
+            pos.col += 1;
+        }
+    }
+    pos.offset = offset;
+    pos
+}
+
+/// Find the byte range of the tag arguments.
+fn find_args(text: &str, start: usize, end: usize) -> Option<(usize, usize)> {
+    let raw = &text[start..end];
+    let inner = raw.strip_prefix('{')?.strip_suffix('}')?;
+    let inner = inner.trim_ascii_start();
+    let inner = inner.strip_prefix('/').unwrap_or(inner).trim_ascii_start();
+    let (_, args) = inner.split_once(|ch: char| ch.is_ascii_whitespace())?;
+    let mut args = args.trim_ascii();
+    if let Some(expr) = args.strip_prefix('=') {
+        args = expr.trim_ascii();
+    }
+    if args.is_empty() {
+        return None;
+    }
+    let args_start = args.as_ptr() as usize - text.as_ptr() as usize;
+    Some((args_start, args_start + args.len()))
+}
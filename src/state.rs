@@ -1,5 +1,7 @@
 use crate::*;
 use alloc::string::String;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
 
 type ID = String;
 
@@ -14,5 +16,18 @@ pub struct State {
 
     pub inventory: Inventory,
     pub vars: Vars,
-    pub effect: TextEffect,
+    /// Text effects opened by tags and not closed yet, from outer to inner.
+    pub effects: Vec<TextEffect>,
+
+    /// ID of the dialog being interpreted.
+    ///
+    /// Used to tell apart list blocks from different dialogs.
+    pub dialog: ID,
+    /// How many times each list block was visited, by dialog ID and block ID.
+    ///
+    /// Blocks from strings printed by `{say}` are counted under the dialog ID
+    /// followed by a line break and the ID of the `{say}` tag.
+    pub visits: HashMap<(ID, u16), u16>,
+    /// Seed for picking items in {shuffle} blocks.
+    pub seed: u32,
 }
@@ -0,0 +1,360 @@
+use crate::*;
+use alloc::vec;
+use alloc::vec::Vec;
+use libm::{floorf, powf};
+
+/// Loudness of a single voice. Two voices playing at once never clip.
+const VOLUME: f32 = 0.25;
+
+/// How long each note of an arpeggiated chord plays, in milliseconds.
+const ARPEGGIO_DURATION: u32 = 50;
+
+/// The maximum level of the blip envelope sustain.
+const MAX_SUSTAIN: f32 = 15.;
+
+/// The longest blip in milliseconds. Longer envelopes are cut.
+const MAX_BLIP_DURATION: u32 = 10_000;
+
+/// The index of the harmony voice in a bar.
+const HARMONY: usize = 1;
+
+/// A single note, like "C4" or "2d5".
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Note {
+    /// How many steps the note lasts.
+    pub len: u8,
+    /// Semitone within the octave, 0 is C and 11 is B.
+    pub pitch: u8,
+    pub octave: u8,
+}
+
+impl Note {
+    /// Parse a note.
+    ///
+    /// The format is an optional length in steps, the note letter, an optional
+    /// "#" for sharp, and an optional octave, like "2C#4". Lowercase letters
+    /// are sharp notes as well. The default length is 1, the default octave is 4.
+    /// Returns `None` for rests and invalid notes.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim_ascii();
+        let digits = s.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(s.len());
+        let len = match &s[..digits] {
+            "" => 1,
+            len => len.parse().ok()?,
+        };
+        let mut chars = s[digits..].chars();
+        let letter = chars.next()?;
+        let mut pitch = match letter.to_ascii_uppercase() {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            _ => return None,
+        };
+        let mut rest = chars.as_str();
+        if let Some(r) = rest.strip_prefix('#') {
+            rest = r;
+            pitch += 1;
+        } else if letter.is_ascii_lowercase() {
+            pitch += 1;
+        }
+        let octave = match rest {
+            "" => 4,
+            octave => octave.parse().ok()?,
+        };
+        // B# is C of the next octave.
+        let octave = octave + pitch / 12;
+        let pitch = pitch % 12;
+        Some(Self { len, pitch, octave })
+    }
+
+    /// The note the given number of semitones higher.
+    fn shift(&self, semitones: u8) -> Self {
+        let pitch = self.pitch + semitones;
+        Self {
+            len: self.len,
+            pitch: pitch % 12,
+            octave: self.octave.saturating_add(pitch / 12),
+        }
+    }
+
+    /// The closest higher note with the given pitch.
+    fn up_to(&self, pitch: u8) -> Self {
+        self.shift((pitch + 12 - self.pitch) % 12)
+    }
+
+    /// The note frequency in Hz. A4 is 440 Hz.
+    pub fn freq(&self) -> f32 {
+        let midi = (i32::from(self.octave) + 1) * 12 + i32::from(self.pitch);
+        440. * powf(2., (midi - 69) as f32 / 12.)
+    }
+}
+
+/// Tune tempo, from the TMP property.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Tempo {
+    Slow,
+    #[default]
+    Medium,
+    Fast,
+    ExtraFast,
+}
+
+impl Tempo {
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "SLW" => Self::Slow,
+            "FST" => Self::Fast,
+            "XFST" => Self::ExtraFast,
+            _ => Self::Medium,
+        }
+    }
+
+    /// The duration of a single step in milliseconds.
+    pub fn step_duration(&self) -> u32 {
+        match self {
+            Self::Slow => 250,
+            Self::Medium => 200,
+            Self::Fast => 125,
+            Self::ExtraFast => 100,
+        }
+    }
+}
+
+/// Pulse width of a square wave, from the SQR property.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Duty {
+    /// 50%.
+    #[default]
+    P2,
+    /// 25%.
+    P4,
+    /// 12.5%.
+    P8,
+}
+
+impl Duty {
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "P4" => Self::P4,
+            "P8" => Self::P8,
+            _ => Self::P2,
+        }
+    }
+
+    /// The part of the period when the wave is high.
+    pub fn ratio(&self) -> f32 {
+        match self {
+            Self::P2 => 0.5,
+            Self::P4 => 0.25,
+            Self::P8 => 0.125,
+        }
+    }
+}
+
+/// How harmony notes are played, from the ARP property.
+///
+/// Each harmony note is played as a triad built on it from the tune key:
+/// the note, then the third and the fifth notes of the key scale above it.
+/// If the tune has no KEY or the note is not in the key, the major triad
+/// is used instead. This is an approximation of what Bitsy plays.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Arpeggio {
+    /// Notes are played as is.
+    #[default]
+    Off,
+    /// Notes of the triad play one after another, from low to high.
+    Up,
+    /// Notes of the triad play one after another, from high to low.
+    Down,
+}
+
+impl Arpeggio {
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "UP" => Self::Up,
+            "DWN" | "DOWN" => Self::Down,
+            _ => Self::Off,
+        }
+    }
+
+    /// The notes played in place of the given one.
+    ///
+    /// The key is the list of pitches of the key scale.
+    fn notes(&self, note: Note, key: &[u8]) -> Vec<Note> {
+        let index = key.iter().position(|pitch| *pitch == note.pitch);
+        let triad = match index {
+            Some(i) if key.len() >= 3 => {
+                let third = key[(i + 2) % key.len()];
+                let fifth = key[(i + 4) % key.len()];
+                [note, note.up_to(third), note.up_to(fifth)]
+            }
+            _ => [note, note.shift(4), note.shift(7)],
+        };
+        match self {
+            Self::Off => vec![note],
+            Self::Up => triad.to_vec(),
+            Self::Down => triad.into_iter().rev().collect(),
+        }
+    }
+}
+
+/// Renders tunes and blips into mono PCM samples in the -1..=1 range.
+pub struct Synth {
+    /// Samples per second.
+    pub sample_rate: u32,
+}
+
+impl Synth {
+    pub fn new(sample_rate: u32) -> Self {
+        Self { sample_rate }
+    }
+
+    /// Render the whole tune once.
+    ///
+    /// Each line of a bar is a separate voice: the first one is the melody,
+    /// the second one is the harmony. Voices play at the same time.
+    pub fn tune(&self, tune: &Tune) -> Vec<f32> {
+        let tempo = Tempo::parse(tune.prop("TMP").unwrap_or_default());
+        let arp = Arpeggio::parse(tune.prop("ARP").unwrap_or_default());
+        let key = parse_key(tune.prop("KEY").unwrap_or_default());
+        let duties: Vec<_> = match tune.prop("SQR") {
+            Some(sqr) => sqr.split(' ').map(Duty::parse).collect(),
+            None => Vec::new(),
+        };
+        let step_len = self.samples(tempo.step_duration());
+        let steps: usize = tune.bars.iter().map(bar_len).sum();
+        let mut out = vec![0.; steps * step_len];
+        let mut bar_start = 0;
+        for bar in &tune.bars {
+            for (v, voice) in bar.iter().enumerate() {
+                let duty = duties.get(v).copied().unwrap_or_default();
+                // Only the harmony is played as arpeggios.
+                let arp = if v == HARMONY { arp } else { Arpeggio::Off };
+                for (i, step) in voice.iter().enumerate() {
+                    let Some(note) = Note::parse(step) else {
+                        continue;
+                    };
+                    let start = (bar_start + i) * step_len;
+                    let end = (start + usize::from(note.len) * step_len).min(out.len());
+                    self.play(&mut out[start..end], &arp.notes(note, &key), duty);
+                }
+            }
+            bar_start += bar_len(bar);
+        }
+        out
+    }
+
+    /// Render the blip sound effect.
+    ///
+    /// The ENV property is "attack decay sustain hold release", where the sustain
+    /// is a level from 0 to 15 and the rest are durations in milliseconds.
+    /// The first value of BEAT is the duration of each note in milliseconds.
+    /// If it's 0, notes evenly split the whole duration of the blip.
+    /// If RPT is 1, the notes repeat until the blip is over.
+    pub fn blip(&self, blip: &Blip) -> Vec<f32> {
+        let notes: Vec<_> = blip.notes.iter().filter_map(|n| Note::parse(n)).collect();
+        let env = parse_nums::<5>(blip.prop("ENV").unwrap_or_default());
+        let [attack, decay, sustain, hold, release] = env.map(|v| v.min(MAX_BLIP_DURATION));
+        let beat = parse_nums::<2>(blip.prop("BEAT").unwrap_or_default())[0];
+        let duty = Duty::parse(blip.prop("SQR").unwrap_or_default());
+        let repeat = blip.prop("RPT") == Some("1");
+
+        let duration = [attack, decay, hold, release]
+            .into_iter()
+            .fold(0, u32::saturating_add)
+            .min(MAX_BLIP_DURATION);
+        let total = self.samples(duration);
+        let mut out = vec![0.; total];
+        if notes.is_empty() || total == 0 {
+            return out;
+        }
+        let note_len = match beat {
+            0 => total.div_ceil(notes.len()),
+            beat => self.samples(beat).max(1),
+        };
+        for (i, sample) in out.iter_mut().enumerate() {
+            let mut index = i / note_len;
+            if repeat {
+                index %= notes.len();
+            }
+            let note = notes[index.min(notes.len() - 1)];
+            *sample = self.square(note.freq(), duty, i);
+        }
+
+        // Apply the envelope.
+        let sustain = (sustain as f32 / MAX_SUSTAIN).min(1.);
+        let phases = [
+            (self.samples(attack), 0., 1.),
+            (self.samples(decay), 1., sustain),
+            (self.samples(hold), sustain, sustain),
+            (self.samples(release), sustain, 0.),
+        ];
+        let mut start = 0;
+        for (len, from, to) in phases {
+            for i in 0..len {
+                let Some(sample) = out.get_mut(start + i) else {
+                    break;
+                };
+                *sample *= from + (to - from) * i as f32 / len as f32;
+            }
+            start += len;
+        }
+        out
+    }
+
+    /// Play the notes one after another over the whole buffer,
+    /// adding them to what is already there.
+    fn play(&self, out: &mut [f32], notes: &[Note], duty: Duty) {
+        let arp_len = self.samples(ARPEGGIO_DURATION).max(1);
+        for (i, sample) in out.iter_mut().enumerate() {
+            let note = notes[(i / arp_len) % notes.len()];
+            *sample += self.square(note.freq(), duty, i);
+        }
+    }
+
+    /// The value of a square wave at the given sample.
+    fn square(&self, freq: f32, duty: Duty, i: usize) -> f32 {
+        let t = i as f32 * freq / self.sample_rate as f32;
+        let phase = t - floorf(t);
+        if phase < duty.ratio() {
+            VOLUME
+        } else {
+            -VOLUME
+        }
+    }
+
+    /// Convert milliseconds into the number of samples.
+    fn samples(&self, ms: u32) -> usize {
+        (u64::from(self.sample_rate) * u64::from(ms) / 1000) as usize
+    }
+}
+
+/// The number of steps in the bar: the length of the longest voice.
+fn bar_len(bar: &Bar) -> usize {
+    bar.iter().map(Vec::len).max().unwrap_or_default()
+}
+
+/// Parse pitches of the key scale from the KEY property, like "C,D,E,F,G,A,B d,g".
+///
+/// Only the first part, the notes of the key, is used.
+fn parse_key(s: &str) -> Vec<u8> {
+    let notes = s.split_ascii_whitespace().next().unwrap_or_default();
+    notes
+        .split(',')
+        .filter_map(Note::parse)
+        .map(|note| note.pitch)
+        .collect()
+}
+
+/// Parse space-separated numbers. Missing and invalid numbers are zeros.
+fn parse_nums<const N: usize>(s: &str) -> [u32; N] {
+    let mut nums = [0; N];
+    for (n, part) in nums.iter_mut().zip(s.split_ascii_whitespace()) {
+        *n = part.parse().unwrap_or_default();
+    }
+    nums
+}
@@ -0,0 +1,130 @@
+use crate::*;
+
+const HI: f32 = 0.25;
+const LO: f32 = -0.25;
+
+fn tune(bars: &[&[&str]], props: &[(&str, &str)]) -> Tune {
+    let split = |voice: &&str| voice.split(',').map(ToString::to_string).collect();
+    Tune {
+        id: "1".to_string(),
+        bars: bars
+            .iter()
+            .map(|bar| bar.iter().map(split).collect())
+            .collect(),
+        props: props
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect(),
+        ..Tune::default()
+    }
+}
+
+#[test]
+fn test_synth_note() {
+    let note = |len, pitch, octave| Some(Note { len, pitch, octave });
+    assert_eq!(Note::parse("C4"), note(1, 0, 4));
+    assert_eq!(Note::parse("2d5"), note(2, 3, 5));
+    assert_eq!(Note::parse("A#3"), note(1, 10, 3));
+    assert_eq!(Note::parse("B#4"), note(1, 0, 5));
+    assert_eq!(Note::parse("G"), note(1, 7, 4));
+    assert_eq!(Note::parse("0"), None);
+    assert_eq!(Note::parse(""), None);
+    assert_eq!(Note::parse("H4"), None);
+    assert_eq!(Note::parse("C4x"), None);
+
+    assert_eq!(Note::parse("A4").unwrap().freq(), 440.);
+    assert_eq!(Note::parse("A5").unwrap().freq(), 880.);
+    let c4 = Note::parse("C4").unwrap().freq();
+    assert!((c4 - 261.626).abs() < 0.01);
+}
+
+#[test]
+fn test_synth_props() {
+    assert_eq!(Tempo::parse("SLW").step_duration(), 250);
+    assert_eq!(Tempo::parse("what"), Tempo::Medium);
+    assert_eq!(Duty::parse("P8").ratio(), 0.125);
+    assert_eq!(Duty::parse(""), Duty::P2);
+    assert_eq!(Arpeggio::parse("DWN"), Arpeggio::Down);
+    assert_eq!(Arpeggio::parse("OFF"), Arpeggio::Off);
+}
+
+#[test]
+fn test_synth_tune() {
+    let synth = Synth::new(1000);
+    // A4 at 1000 Hz sample rate: the phase moves by 0.44 each sample.
+    let t = tune(&[&["A4,0"], &["2A4,0,0"]], &[("TMP", "FST")]);
+    let out = synth.tune(&t);
+    assert_eq!(out.len(), 5 * 125);
+    assert_eq!(&out[..5], &[HI, HI, LO, HI, LO]);
+    assert!(out[..125].iter().all(|s| s.abs() == HI));
+    assert!(out[125..250].iter().all(|s| *s == 0.));
+    assert!(out[250..500].iter().all(|s| s.abs() == HI));
+    assert!(out[500..].iter().all(|s| *s == 0.));
+
+    // Two voices are mixed, P4 duty for the second one.
+    let t = tune(&[&["A4", "A4"]], &[("TMP", "FST"), ("SQR", "P2 P4")]);
+    let out = synth.tune(&t);
+    assert_eq!(&out[..4], &[2. * HI, 0., 2. * LO, 0.]);
+
+    // Without a key, the harmony plays the major triad on A4 (A4, C#5, E5) note by note.
+    let t = tune(&[&["0", "A4"]], &[("ARP", "UP")]);
+    let out = synth.tune(&t);
+    assert_eq!(out.len(), 200);
+    assert_eq!(&out[..3], &[HI, HI, LO]);
+    // C#5 after 50 ms: the phase is 0.72, 0.27, 0.83.
+    assert_eq!(&out[50..53], &[LO, HI, LO]);
+    // E5 after 100 ms: the phase is 0.93, 0.58, 0.24.
+    assert_eq!(&out[100..103], &[LO, LO, HI]);
+    // A4 again after 150 ms.
+    assert_eq!(&out[150..153], &[HI, HI, LO]);
+    // E5 goes first when going down: the phase is 0, 0.66, 0.32.
+    let t = tune(&[&["0", "A4"]], &[("ARP", "DWN")]);
+    assert_eq!(&synth.tune(&t)[..3], &[HI, LO, HI]);
+
+    // With a key, the triad is built from the key scale: A4, C5, E5 in C major.
+    let key = ("KEY", "C,D,E,F,G,A,B d,g");
+    let t = tune(&[&["0", "A4"]], &[("ARP", "UP"), key]);
+    let out = synth.tune(&t);
+    assert_eq!(&out[..3], &[HI, HI, LO]);
+    // C5 after 50 ms: the phase is 0.16, 0.69, 0.21.
+    assert_eq!(&out[50..53], &[HI, LO, HI]);
+    assert_eq!(&out[100..103], &[LO, LO, HI]);
+
+    // The melody is never arpeggiated.
+    let t = tune(&[&["A4"]], &[("ARP", "UP")]);
+    assert_eq!(&synth.tune(&t)[50..53], &[HI, HI, LO]);
+}
+
+#[test]
+fn test_synth_blip() {
+    let synth = Synth::new(1000);
+    let mut blip = Blip {
+        id: "1".to_string(),
+        notes: vec!["A4".to_string(), "A2".to_string()],
+        props: vec![
+            ("ENV".to_string(), "2 0 15 4 2".to_string()),
+            ("BEAT".to_string(), "2 0".to_string()),
+        ],
+        ..Blip::default()
+    };
+    let out = synth.blip(&blip);
+    assert_eq!(out.len(), 8);
+    // Attack, then hold at the full volume, then release.
+    // The last note plays until the end.
+    assert_eq!(out, vec![0., HI * 0.5, HI, HI, HI, LO, LO, LO * 0.5]);
+
+    blip.props.push(("RPT".to_string(), "1".to_string()));
+    let out = synth.blip(&blip);
+    assert_eq!(out, vec![0., HI * 0.5, HI, HI, LO, HI, LO, LO * 0.5]);
+
+    blip.props[0].1 = String::new();
+    assert!(synth.blip(&blip).is_empty());
+
+    // Huge envelopes are cut instead of overflowing.
+    blip.props[0].1 = "4294967295 1 1 1 1".to_string();
+    assert_eq!(synth.blip(&blip).len(), 10_000);
+    blip.props[0].1 = "4294967295 4294967295 99 4294967295 4294967295".to_string();
+    let out = synth.blip(&blip);
+    assert_eq!(out.len(), 10_000);
+    assert!(out.iter().all(|s| s.abs() <= HI));
+}
@@ -1,6 +1,10 @@
-use crate::Val;
+use crate::*;
+use alloc::boxed::Box;
+use alloc::format;
 use alloc::string::String;
 use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::mem::discriminant;
 use core::str::Chars;
 
 pub type ID = String;
@@ -20,6 +24,65 @@ pub enum TextEffect {
     Color(u8),
 }
 
+/// Room transition effect.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transition {
+    /// Fade to white.
+    FadeW,
+    /// Fade to black.
+    FadeB,
+    Wave,
+    Tunnel,
+    SlideU,
+    SlideD,
+    SlideL,
+    SlideR,
+    /// Transition not supported by Bitsy.
+    Other(String),
+}
+
+impl Transition {
+    /// Parse the transition name, like "fade_w".
+    pub fn parse(name: &str) -> Self {
+        match name {
+            "fade_w" => Self::FadeW,
+            "fade_b" => Self::FadeB,
+            "wave" => Self::Wave,
+            "tunnel" => Self::Tunnel,
+            "slide_u" => Self::SlideU,
+            "slide_d" => Self::SlideD,
+            "slide_l" => Self::SlideL,
+            "slide_r" => Self::SlideR,
+            _ => Self::Other(name.to_string()),
+        }
+    }
+
+    /// The transition name as used in Bitsy.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::FadeW => "fade_w",
+            Self::FadeB => "fade_b",
+            Self::Wave => "wave",
+            Self::Tunnel => "tunnel",
+            Self::SlideU => "slide_u",
+            Self::SlideD => "slide_d",
+            Self::SlideL => "slide_l",
+            Self::SlideR => "slide_r",
+            Self::Other(name) => name,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SeqKind {
+    /// {sequence} shows items one by one and then keeps showing the last one.
+    Sequence,
+    /// {cycle} shows items one by one and then starts over.
+    Cycle,
+    /// {shuffle} shows items in random order.
+    Shuffle,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Tag {
     /// Line break.
@@ -31,7 +94,10 @@ pub enum Tag {
     /// End the game.
     End,
     /// Print the result of expression.
-    Say(Expr),
+    ///
+    /// The first value is the ID of the tag. It's counted together with block IDs,
+    /// so that blocks in the printed strings can be told apart.
+    Say(u16, Expr),
     /// Draw tile.
     DrwT(ID),
     /// Draw sprite.
@@ -42,10 +108,31 @@ pub enum Tag {
     Pal(ID),
     /// Make avatar look like the given sprite.
     Ava(ID),
-    /// Move player to the given room.
-    Exit(ID, u8, u8),
+    /// Move player to the given room, with an optional transition effect.
+    Exit(ID, u8, u8, Option<Transition>),
+    /// Play the tune. The tune "0" stops the music.
+    Tune(ID),
+    /// Play the blip sound effect.
+    Blip(ID),
     /// Evaluate the expression and assign its result to the variable.
     Set(String, Expr),
+    /// Set how many items of the given kind the player has.
+    ///
+    /// Without the count, like `{item "key"}`, the inventory is not changed.
+    Item(ID, Option<Expr>),
+    /// Give the player the given number of items.
+    Give(ID, Expr),
+    /// Take the given number of items from the player.
+    Take(ID, Expr),
+    /// List block. Shows one of the items each time the dialog is visited.
+    ///
+    /// The first value is the ID of the block, unique within the dialog.
+    Seq(u16, SeqKind, Vec<Vec<Token>>),
+    /// Conditional block. Shows the first branch which condition is true.
+    ///
+    /// As in Bitsy, a condition is true only if its value equals 1,
+    /// see [`Val::is_truthy`]. The branch without a condition is the "else" branch.
+    If(Vec<(Option<Expr>, Vec<Token>)>),
     /// Unsupported tag.
     Unknown(String, String),
 }
@@ -53,7 +140,9 @@ pub enum Tag {
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     SimpleExpr(SimpleExpr),
-    BinOp(BinOp, SimpleExpr, SimpleExpr),
+    BinOp(BinOp, Box<Expr>, Box<Expr>),
+    /// Unary minus.
+    Neg(Box<Expr>),
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -74,6 +163,8 @@ pub enum SimpleExpr {
     Var(String),
     Item(String),
     Val(Val),
+    /// Call of a custom function, like `{myfunc 1 "a"}`.
+    Call(String, Vec<Expr>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -84,16 +175,114 @@ pub enum Token {
 }
 
 pub struct Tokenizer<'a> {
+    text: &'a str,
     buffer: Chars<'a>,
     stash: Option<char>,
+    /// How many list blocks were emitted so far. Used to assign block IDs.
+    blocks: u16,
+    /// Byte offset of the text in the source. Non-zero for nested tokenizers.
+    base: usize,
+    /// Effects opened so far, used to match closing tags.
+    effects: Vec<TextEffect>,
+    diagnostics: Vec<Diagnostic>,
+    /// Skip indentation at the beginning of each line. Used for block items.
+    trim_lines: bool,
+    /// If the last token was a line break in the source text.
+    line_start: bool,
+    /// Custom functions which tags are not reported as unknown.
+    funcs: Option<&'a Functions>,
+}
+
+/// Context for parsing the content of a tag.
+struct Ctx<'a> {
+    /// The raw text of the tag, including curly braces.
+    raw: &'a str,
+    /// Byte offset of the tag in the source.
+    base: usize,
+    blocks: &'a mut u16,
+    diagnostics: &'a mut Vec<Diagnostic>,
+    funcs: Option<&'a Functions>,
+}
+
+impl Ctx<'_> {
+    /// Get the byte range in the source for a part of the tag text.
+    fn range(&self, part: &str) -> (usize, usize) {
+        let start = self.base + (part.as_ptr() as usize - self.raw.as_ptr() as usize);
+        (start, start + part.len())
+    }
+
+    /// Report a problem with the given part of the tag text.
+    fn report(&mut self, code: Code, message: String, part: &str) {
+        let (start, end) = self.range(part);
+        let diagnostic = Diagnostic::new(code, message, start, end);
+        self.diagnostics.push(diagnostic);
+    }
 }
 
 impl<'a> Tokenizer<'a> {
     pub fn new(text: &'a str) -> Self {
         Self {
+            text,
             buffer: text.chars(),
             stash: None,
+            blocks: 0,
+            base: 0,
+            effects: Vec::new(),
+            diagnostics: Vec::new(),
+            trim_lines: false,
+            line_start: false,
+            funcs: None,
+        }
+    }
+
+    /// Don't report tags of the given custom functions as unknown.
+    pub fn with_functions(mut self, funcs: &'a Functions) -> Self {
+        self.funcs = Some(funcs);
+        self
+    }
+
+    /// Emit tokens together with their position in the source text.
+    pub fn spanned(self) -> Spanned<'a> {
+        Spanned::new(self)
+    }
+
+    /// Tokenizer for the text of a list item.
+    ///
+    /// The block numbering starts from the given ID, so that nested blocks
+    /// get unique IDs as well. Like in Bitsy, lines of the item are trimmed,
+    /// so the indentation is not shown.
+    pub(crate) fn nested(text: &'a str, blocks: u16) -> Self {
+        let mut tokenizer = Self::new(text);
+        tokenizer.trim_lines = true;
+        tokenizer.blocks = blocks;
+        tokenizer
+    }
+
+    /// The source text being tokenized.
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+
+    /// How many list blocks were emitted so far.
+    pub(crate) fn blocks(&self) -> u16 {
+        self.blocks
+    }
+
+    /// Problems found in the text tokenized so far.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = self.diagnostics.clone();
+        for diagnostic in &mut diagnostics {
+            let span = &mut diagnostic.span;
+            span.start = pos_at(self.text, span.start.offset);
+            span.end = pos_at(self.text, span.end.offset);
         }
+        diagnostics
+    }
+
+    /// Byte offset in the source text of the next token.
+    pub fn offset(&self) -> usize {
+        let stash_len = self.stash.map(char::len_utf8).unwrap_or_default();
+        self.text.len() - self.buffer.as_str().len() - stash_len
     }
 }
 
@@ -101,6 +290,19 @@ impl<'a> Iterator for Tokenizer<'a> {
     type Item = Token;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.trim_lines && core::mem::take(&mut self.line_start) {
+            let rest = self.buffer.as_str().trim_start_matches([' ', '\t']);
+            self.buffer = rest.chars();
+        }
+        let start = self.offset();
+        let token = self.next_token(start)?;
+        self.check_closing(&token, start);
+        Some(token)
+    }
+}
+
+impl Tokenizer<'_> {
+    fn next_token(&mut self, start: usize) -> Option<Token> {
         let mut word = String::new();
         let mut found_letter = false;
         let mut open_tags: u8 = 0;
@@ -114,26 +316,35 @@ impl<'a> Iterator for Tokenizer<'a> {
             };
             word.push(ch);
             match ch {
-                '\n' => {
-                    if open_tags == 0 && found_letter {
+                '\n' if open_tags == 0 => {
+                    if found_letter {
                         self.stash = Some('\n');
+                        word.pop();
                         break;
                     }
+                    self.line_start = true;
                     return Some(Token::OpenTag(Tag::Br));
                 }
                 '{' => {
-                    if open_tags == 0 && found_letter {
+                    if open_tags == 0 && word.len() > 1 {
                         self.stash = Some('{');
                         word.pop();
                         break;
                     }
-                    open_tags += 1;
+                    open_tags = open_tags.saturating_add(1);
                 }
                 '}' => {
                     if open_tags != 0 {
                         open_tags -= 1;
                         if open_tags == 0 {
-                            return Some(parse_tag(&word));
+                            let mut ctx = Ctx {
+                                raw: &word,
+                                base: self.base + start,
+                                blocks: &mut self.blocks,
+                                diagnostics: &mut self.diagnostics,
+                                funcs: self.funcs,
+                            };
+                            return Some(parse_tag(&mut ctx));
                         } else {
                             found_letter = true
                         }
@@ -150,20 +361,55 @@ impl<'a> Iterator for Tokenizer<'a> {
         if word.is_empty() {
             return None;
         }
+        if open_tags != 0 {
+            let start = self.base + start;
+            let msg = "the tag is not closed".to_string();
+            let diagnostic = Diagnostic::new(Code::UnclosedTag, msg, start, start + word.len());
+            self.diagnostics.push(diagnostic);
+        }
         Some(Token::Word(word))
     }
+
+    /// Report closing tags that don't have a matching opening tag.
+    fn check_closing(&mut self, token: &Token, start: usize) {
+        match token {
+            Token::OpenTag(Tag::Eff(eff)) => {
+                self.effects.push(*eff);
+                return;
+            }
+            Token::CloseTag(Tag::Eff(eff)) => {
+                let kind = discriminant(eff);
+                let found = self.effects.iter().rposition(|e| discriminant(e) == kind);
+                if let Some(index) = found {
+                    self.effects.remove(index);
+                    return;
+                }
+            }
+            Token::CloseTag(_) => {}
+            _ => return,
+        };
+        let start = self.base + start;
+        let end = self.base + self.offset();
+        let msg = "the closing tag doesn't have a matching opening tag".to_string();
+        let diagnostic = Diagnostic::new(Code::UnmatchedClose, msg, start, end);
+        self.diagnostics.push(diagnostic);
+    }
 }
 
-fn parse_tag(word: &str) -> Token {
+fn parse_tag(ctx: &mut Ctx) -> Token {
+    let word = ctx.raw;
     let word = &word[..word.len() - 1]; // remove "}" from the end.
     let mut word = &word[1..]; // remove "{" from the beginning.
     word = word.trim_ascii();
+    if let Some(tag) = parse_block(word, ctx) {
+        return Token::OpenTag(tag);
+    }
     let is_closing = word.starts_with('/');
     if is_closing {
         word = &word[1..];
         word = word.trim_ascii();
     }
-    let tag = parse_tag_value(word);
+    let tag = parse_tag_value(word, ctx);
     if is_closing {
         Token::CloseTag(tag)
     } else {
@@ -171,19 +417,157 @@ fn parse_tag(word: &str) -> Token {
     }
 }
 
-fn parse_tag_value(word: &str) -> Tag {
+/// Try parsing the tag as a block containing list items.
+fn parse_block(word: &str, ctx: &mut Ctx) -> Option<Tag> {
+    if word.starts_with('-') {
+        return Some(parse_cond(word, ctx));
+    }
+    let (name, body) = word
+        .split_once(|ch: char| ch.is_ascii_whitespace())
+        .unwrap_or((word, ""));
+    let kind = match name {
+        "sequence" => SeqKind::Sequence,
+        "cycle" => SeqKind::Cycle,
+        "shuffle" => SeqKind::Shuffle,
+        _ => return None,
+    };
+    let id = *ctx.blocks;
+    // Blocks after the last possible ID share it, the dialog is unlikely to have that many.
+    *ctx.blocks = ctx.blocks.saturating_add(1);
+    let mut items = Vec::new();
+    for item in split_items(body) {
+        items.push(tokenize_nested(item, ctx));
+    }
+    Some(Tag::Seq(id, kind, items))
+}
+
+/// Parse a conditional block.
+///
+/// Each item of the block has the form of `condition ? text`.
+fn parse_cond(body: &str, ctx: &mut Ctx) -> Tag {
+    let mut branches = Vec::new();
+    for item in split_items(body) {
+        let (cond, text) = split_branch(item);
+        let cond = cond.map(|cond| parse_expr(cond, ctx));
+        branches.push((cond, tokenize_nested(text, ctx)));
+    }
+    Tag::If(branches)
+}
+
+/// Split the item of a conditional block into the condition and the text.
+///
+/// The condition is `None` for the "else" branch.
+fn split_branch(item: &str) -> (Option<&str>, &str) {
+    let (cond, text) = split_cond(item).unwrap_or(("else", item));
+    let cond = cond.trim_ascii();
+    let cond = if cond == "else" { None } else { Some(cond) };
+    (cond, text.trim_ascii())
+}
+
+/// Find items of the block in the raw text of the tag, including curly braces.
+///
+/// Each item is a pair of the branch condition and the item text.
+/// The condition is `None` for list items and "else" branches.
+pub(crate) fn find_items(raw: &str) -> Vec<(Option<&str>, &str)> {
+    let inner = raw.strip_prefix('{').unwrap_or(raw);
+    let inner = inner.strip_suffix('}').unwrap_or(inner).trim_ascii();
+    if inner.starts_with('-') {
+        return split_items(inner).into_iter().map(split_branch).collect();
+    }
+    let (_, body) = inner
+        .split_once(|ch: char| ch.is_ascii_whitespace())
+        .unwrap_or((inner, ""));
+    split_items(body)
+        .into_iter()
+        .map(|item| (None, item))
+        .collect()
+}
+
+/// Split the item of a conditional block at the question mark.
+///
+/// Question marks inside of nested tags and string literals are ignored.
+fn split_cond(item: &str) -> Option<(&str, &str)> {
+    let mut depth: u8 = 0;
+    let mut quoted = false;
+    for (i, ch) in item.char_indices() {
+        match ch {
+            '"' => quoted = !quoted,
+            '{' if !quoted => depth = depth.saturating_add(1),
+            '}' if !quoted => depth = depth.saturating_sub(1),
+            '?' if !quoted && depth == 0 => return Some((&item[..i], &item[i + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split the body of a block into list items.
+///
+/// Each item starts with a dash at the beginning of a line.
+/// Dashes inside of nested tags are ignored.
+fn split_items(body: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut depth: u8 = 0;
+    let mut line_start = true;
+    for (i, ch) in body.char_indices() {
+        match ch {
+            '{' => depth = depth.saturating_add(1),
+            '}' => depth = depth.saturating_sub(1),
+            '-' if depth == 0 && line_start => {
+                if let Some(start) = start {
+                    items.push(body[start..i].trim_ascii());
+                }
+                start = Some(i + 1);
+            }
+            _ => {}
+        }
+        if ch == '\n' {
+            line_start = true;
+        } else if !ch.is_ascii_whitespace() {
+            line_start = false;
+        }
+    }
+    if let Some(start) = start {
+        items.push(body[start..].trim_ascii());
+    }
+    items
+}
+
+/// Tokenize the text of a list item.
+///
+/// The nested tokenizer continues the block numbering of the parent one.
+fn tokenize_nested(text: &str, ctx: &mut Ctx) -> Vec<Token> {
+    let mut tokenizer = Tokenizer::nested(text, *ctx.blocks);
+    tokenizer.funcs = ctx.funcs;
+    (tokenizer.base, _) = ctx.range(text);
+    let tokens = tokenizer.by_ref().collect();
+    *ctx.blocks = tokenizer.blocks;
+    ctx.diagnostics.append(&mut tokenizer.diagnostics);
+    tokens
+}
+
+fn parse_tag_value(word: &str, ctx: &mut Ctx) -> Tag {
     let (name, args) = word.split_once(' ').unwrap_or((word, ""));
     let args = args.trim_ascii();
-    if args.is_empty() {
+    let tag = if args.is_empty() {
         parse_tag_without_args(name)
     } else {
-        parse_tag_with_args(name, args)
+        parse_tag_with_args(name, args, ctx)
+    };
+    let registered = ctx.funcs.is_some_and(|funcs| funcs.contains(name));
+    if let Tag::Unknown(..) = tag
+        && !registered
+    {
+        let msg = format!("unknown function: {name}");
+        ctx.report(Code::UnknownFunc, msg, name);
     }
+    tag
 }
 
-fn parse_tag_with_args(name: &str, args: &str) -> Tag {
+fn parse_tag_with_args(name: &str, args: &str, ctx: &mut Ctx) -> Tag {
     if args.starts_with('=') {
-        return parse_assign(name, args);
+        return parse_assign(name, args, ctx);
     }
     match name {
         "clr" => match args {
@@ -192,16 +576,34 @@ fn parse_tag_with_args(name: &str, args: &str) -> Tag {
             "2" => Tag::Eff(TextEffect::Color(3)),
             _ => Tag::Eff(TextEffect::Color(1)),
         },
-        "say" | "print" => Tag::Say(parse_expr(args)),
+        "say" | "print" => {
+            let id = *ctx.blocks;
+            *ctx.blocks = ctx.blocks.saturating_add(1);
+            Tag::Say(id, parse_expr(args, ctx))
+        }
         "drwt" | "printTile" => Tag::DrwT(unquote(args).to_string()),
         "drws" | "printSprite" => Tag::DrwS(unquote(args).to_string()),
         "drwi" | "printItem" => Tag::DrwI(unquote(args).to_string()),
         "ava" => Tag::Ava(unquote(args).to_string()),
         "pal" => Tag::Pal(unquote(args).to_string()),
+        "tune" => Tag::Tune(unquote(args).to_string()),
+        "blip" => Tag::Blip(unquote(args).to_string()),
+        "item" => {
+            let (id, count) = parse_item_args(args, ctx);
+            Tag::Item(id, count)
+        }
+        "give" => {
+            let (id, count) = parse_item_args(args, ctx);
+            Tag::Give(id, count.unwrap_or(ONE))
+        }
+        "take" => {
+            let (id, count) = parse_item_args(args, ctx);
+            Tag::Take(id, count.unwrap_or(ONE))
+        }
         "exit" => {
-            let (room, x, y) = parse_exit_args(args);
+            let (room, x, y, fx) = parse_exit_args(args, ctx);
             let room = room.to_string();
-            Tag::Exit(room, x, y)
+            Tag::Exit(room, x, y, fx)
         }
         _ => Tag::Unknown(name.to_string(), args.to_string()),
     }
@@ -223,139 +625,340 @@ fn parse_tag_without_args(name: &str) -> Tag {
     }
 }
 
-fn parse_assign(name: &str, args: &str) -> Tag {
+fn parse_assign(name: &str, args: &str, ctx: &mut Ctx) -> Tag {
     let args = &args[1..];
-    let expr = parse_expr(args);
+    let expr = parse_expr(args, ctx);
     Tag::Set(name.to_string(), expr)
 }
 
-fn parse_expr(args: &str) -> Expr {
+/// Parse the expression.
+///
+/// If the expression cannot be parsed, the whole text is treated as a string.
+fn parse_expr(args: &str, ctx: &mut Ctx) -> Expr {
     let args = args.trim_ascii();
-    if let Some(expr) = parse_bin_op(args) {
-        expr
-    } else {
-        Expr::SimpleExpr(parse_simple_expr(args))
+    match try_parse_expr(args) {
+        Ok(expr) => return expr,
+        Err(ExprError::InvalidOp(op)) => {
+            let msg = format!("invalid operator: {op}");
+            ctx.report(Code::InvalidOp, msg, op);
+        }
+        Err(ExprError::Syntax) => {}
     }
+    Expr::SimpleExpr(SimpleExpr::Val(Val::S(args.to_string())))
 }
 
-/// Try parsing the expression as a binary operation.
-fn parse_bin_op(args: &str) -> Option<Expr> {
-    let (left, op, right) = split_bin_op(args)?;
-    let left = parse_simple_expr(left);
-    let right = parse_simple_expr(right);
-    let op = op.trim_ascii();
-    let Some(op) = parse_operator(op) else {
-        let val = Val::S(args.to_string());
-        return Some(Expr::SimpleExpr(SimpleExpr::Val(val)));
-    };
-    Some(Expr::BinOp(op, left, right))
+enum ExprError<'a> {
+    /// The text is not an expression.
+    Syntax,
+    /// The text looks like an expression but has an invalid operator.
+    InvalidOp(&'a str),
 }
 
-fn parse_operator(op: &str) -> Option<BinOp> {
-    match op {
-        "*" => Some(BinOp::Mul),
-        "/" => Some(BinOp::Div),
-        "+" => Some(BinOp::Add),
-        "-" => Some(BinOp::Sub),
-        "<" => Some(BinOp::Lt),
-        ">" => Some(BinOp::Gt),
-        "<=" => Some(BinOp::Lte),
-        ">=" => Some(BinOp::Gte),
-        "==" => Some(BinOp::Eq),
-        _ => None,
+fn try_parse_expr(args: &str) -> Result<Expr, ExprError<'_>> {
+    let lexemes = lex_expr(args)?;
+    let mut parser = ExprParser { lexemes, pos: 0 };
+    let expr = parser.parse_level(0).ok_or(ExprError::Syntax)?;
+    if parser.pos != parser.lexemes.len() {
+        return Err(ExprError::Syntax);
     }
+    Ok(expr)
 }
 
-/// Try splitting the input expression at the first binary operator.
-fn split_bin_op(args: &str) -> Option<(&str, &str, &str)> {
-    let mut prev_op = false;
-    let mut found_term = false;
-    for (i, ch) in args.char_indices() {
-        let cur_op = is_bin_op(ch);
-        if found_term && prev_op {
-            let (left, right) = args.split_at(i);
-            let op_len = if cur_op { 2 } else { 1 };
-            let (left, op) = left.split_at(i - op_len);
-            return Some((left, op, right));
+/// A lexical token of an expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Lexeme {
+    Term(SimpleExpr),
+    Op(BinOp),
+    Open,
+    Close,
+}
+
+/// Split the expression into lexemes.
+fn lex_expr(args: &str) -> Result<Vec<Lexeme>, ExprError<'_>> {
+    let mut lexemes = Vec::new();
+    let mut chars = args.char_indices().peekable();
+    while let Some((start, ch)) = chars.next() {
+        let lexeme = match ch {
+            '\t' | '\n' | '\x0C' | '\r' | ' ' => continue,
+            '(' => Lexeme::Open,
+            ')' => Lexeme::Close,
+            '*' => Lexeme::Op(BinOp::Mul),
+            '/' => Lexeme::Op(BinOp::Div),
+            '+' => Lexeme::Op(BinOp::Add),
+            '-' => Lexeme::Op(BinOp::Sub),
+            '<' | '>' | '=' => {
+                let eq = chars.next_if(|(_, ch)| *ch == '=').is_some();
+                let op = match (ch, eq) {
+                    ('<', false) => BinOp::Lt,
+                    ('<', true) => BinOp::Lte,
+                    ('>', false) => BinOp::Gt,
+                    ('>', true) => BinOp::Gte,
+                    ('=', true) => BinOp::Eq,
+                    _ => return Err(ExprError::InvalidOp(&args[start..start + 1])),
+                };
+                Lexeme::Op(op)
+            }
+            '!' if chars.next_if(|(_, ch)| *ch == '=').is_some() => {
+                return Err(ExprError::InvalidOp(&args[start..start + 2]));
+            }
+            '"' => {
+                let (end, _) = chars.find(|(_, ch)| *ch == '"').ok_or(ExprError::Syntax)?;
+                let val = Val::S(args[start + 1..end].to_string());
+                Lexeme::Term(SimpleExpr::Val(val))
+            }
+            '{' => {
+                let mut depth: u8 = 1;
+                let end = chars.find(|(_, ch)| {
+                    match ch {
+                        '{' => depth = depth.saturating_add(1),
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    depth == 0
+                });
+                let (end, _) = end.ok_or(ExprError::Syntax)?;
+                let expr = parse_call(&args[start + 1..end]).ok_or(ExprError::Syntax)?;
+                Lexeme::Term(expr)
+            }
+            _ => {
+                let mut end = args.len();
+                while let Some((i, ch)) = chars.peek() {
+                    if !ch.is_ascii_alphanumeric() && *ch != '_' && *ch != '.' {
+                        end = *i;
+                        break;
+                    }
+                    chars.next();
+                }
+                let expr = parse_simple_expr(&args[start..end]).ok_or(ExprError::Syntax)?;
+                Lexeme::Term(expr)
+            }
+        };
+        lexemes.push(lexeme);
+    }
+    Ok(lexemes)
+}
+
+/// Recursive descent parser for expressions.
+struct ExprParser {
+    lexemes: Vec<Lexeme>,
+    pos: usize,
+}
+
+impl ExprParser {
+    /// Parse binary operations which precedence is at least the given one.
+    fn parse_level(&mut self, level: u8) -> Option<Expr> {
+        if level > 2 {
+            return self.parse_unary();
         }
-        if i != 0 && ch != ' ' {
-            found_term = true;
+        let mut lhs = self.parse_level(level + 1)?;
+        while let Some(Lexeme::Op(op)) = self.lexemes.get(self.pos) {
+            let op = *op;
+            if precedence(op) != level {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.parse_level(level + 1)?;
+            lhs = Expr::BinOp(op, Box::new(lhs), Box::new(rhs));
         }
-        prev_op = cur_op;
+        Some(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Option<Expr> {
+        let lexeme = self.lexemes.get(self.pos)?.clone();
+        self.pos += 1;
+        match lexeme {
+            Lexeme::Term(expr) => Some(Expr::SimpleExpr(expr)),
+            Lexeme::Op(BinOp::Sub) => {
+                // Negative number literals are parsed as literals.
+                if let Some(Lexeme::Term(SimpleExpr::Val(val))) = self.lexemes.get(self.pos) {
+                    let val = match val {
+                        Val::I(i) => Some(Val::I(i.wrapping_neg())),
+                        Val::F(f) => Some(Val::F(-f)),
+                        _ => None,
+                    };
+                    if let Some(val) = val {
+                        self.pos += 1;
+                        return Some(Expr::SimpleExpr(SimpleExpr::Val(val)));
+                    }
+                }
+                let expr = self.parse_unary()?;
+                Some(Expr::Neg(Box::new(expr)))
+            }
+            Lexeme::Open => {
+                let expr = self.parse_level(0)?;
+                if self.lexemes.get(self.pos) != Some(&Lexeme::Close) {
+                    return None;
+                }
+                self.pos += 1;
+                Some(expr)
+            }
+            Lexeme::Op(_) | Lexeme::Close => None,
+        }
+    }
+}
+
+/// Get the precedence of the binary operator. Higher binds tighter.
+fn precedence(op: BinOp) -> u8 {
+    match op {
+        BinOp::Lt | BinOp::Gt | BinOp::Lte | BinOp::Gte | BinOp::Eq => 0,
+        BinOp::Add | BinOp::Sub => 1,
+        BinOp::Mul | BinOp::Div => 2,
     }
-    None
 }
 
-/// Check if the given character is a binary operator.
+/// Parse a function call used inside of an expression, without curly braces.
+fn parse_call(call: &str) -> Option<SimpleExpr> {
+    let call = call.trim_ascii();
+    let (name, args) = call.split_once(' ').unwrap_or((call, ""));
+    match name {
+        "item" => {
+            let name = unquote(args.trim_ascii());
+            Some(SimpleExpr::Item(name.to_string()))
+        }
+        _ if is_var(name) => Some(SimpleExpr::Call(name.to_string(), parse_args(args))),
+        _ => None,
+    }
+}
+
+/// Parse space-separated arguments of a custom function.
 ///
-/// Keep in mind that some operators require 2 characters,
-/// so two consecutive characters should be checked to correctly
-/// select the operator.
-fn is_bin_op(ch: char) -> bool {
-    matches!(ch, '*' | '/' | '+' | '-' | '<' | '>' | '=')
+/// Arguments that are not valid expressions are treated as strings.
+pub(crate) fn parse_args(args: &str) -> Vec<Expr> {
+    let mut exprs = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut depth: u8 = 0;
+    let mut quoted = false;
+    for (i, ch) in args.char_indices() {
+        match ch {
+            '"' => quoted = !quoted,
+            '{' | '(' if !quoted => depth = depth.saturating_add(1),
+            '}' | ')' if !quoted => depth = depth.saturating_sub(1),
+            _ if ch.is_ascii_whitespace() && !quoted && depth == 0 => {
+                if let Some(start) = start.take() {
+                    exprs.push(parse_arg(&args[start..i]));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(i);
+    }
+    if let Some(start) = start {
+        exprs.push(parse_arg(&args[start..]));
+    }
+    exprs
 }
 
-fn parse_simple_expr(part: &str) -> SimpleExpr {
-    let part = part.trim_ascii();
-    if let Some(name) = part.strip_prefix("{item ") {
-        let name = name.strip_suffix('}').unwrap_or(name);
-        let name = name.trim_ascii();
-        let name = unquote(name);
-        return SimpleExpr::Item(name.to_string());
+fn parse_arg(arg: &str) -> Expr {
+    match try_parse_expr(arg) {
+        Ok(expr) => expr,
+        Err(_) => Expr::SimpleExpr(SimpleExpr::Val(Val::S(arg.to_string()))),
     }
+}
+
+/// Parse a literal or a variable name.
+fn parse_simple_expr(part: &str) -> Option<SimpleExpr> {
     if part == "true" {
-        return SimpleExpr::Val(Val::I(1));
+        return Some(SimpleExpr::Val(Val::I(1)));
     }
     if part == "false" {
-        return SimpleExpr::Val(Val::I(0));
+        return Some(SimpleExpr::Val(Val::I(0)));
     }
     if let Ok(i) = part.parse::<i16>() {
-        return SimpleExpr::Val(Val::I(i));
+        return Some(SimpleExpr::Val(Val::I(i)));
     }
     if let Ok(f) = part.parse::<f32>() {
-        return SimpleExpr::Val(Val::F(f));
-    }
-    if part.starts_with('"') {
-        return SimpleExpr::Val(Val::S(unquote(part).to_string()));
+        return Some(SimpleExpr::Val(Val::F(f)));
     }
     if is_var(part) {
-        return SimpleExpr::Var(part.to_string());
+        return Some(SimpleExpr::Var(part.to_string()));
     }
-    SimpleExpr::Val(Val::S(part.to_string()))
+    None
 }
 
 /// Check if the given string is a valid variable name.
 fn is_var(part: &str) -> bool {
-    let mut first = true;
-    for ch in part.chars() {
-        if !first && ch.is_ascii_digit() {
-            return false;
-        }
-        first = false;
-        if ch.is_ascii_alphanumeric() {
-            continue;
-        }
-        if ch == '_' {
-            continue;
-        }
+    let mut chars = part.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+    if !first.is_ascii_alphabetic() && first != '_' {
         return false;
     }
-    true
+    chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+/// The default count for `give` and `take`.
+const ONE: Expr = Expr::SimpleExpr(SimpleExpr::Val(Val::I(1)));
+
+/// Parse arguments of the `item`, `give`, and `take` functions.
+///
+/// The first argument is the item ID, quoted or not. The rest is the count.
+fn parse_item_args(args: &str, ctx: &mut Ctx) -> (ID, Option<Expr>) {
+    let (id, count) = match args.strip_prefix('"') {
+        Some(rest) => match rest.split_once('"') {
+            Some((id, count)) => (id, count),
+            None => (args, ""),
+        },
+        None => args
+            .split_once(|ch: char| ch.is_ascii_whitespace())
+            .unwrap_or((args, "")),
+    };
+    let count = count.trim_ascii();
+    let count = if count.is_empty() {
+        None
+    } else {
+        Some(parse_expr(count, ctx))
+    };
+    (id.to_string(), count)
 }
 
 /// Parse arguments of the `exit` function.
 ///
 /// Old form: `{exit "id,2,3"}`. New form: `{exit "id",2,3}`.
-fn parse_exit_args(args: &str) -> (&str, u8, u8) {
+/// Both forms accept an optional transition effect at the end.
+fn parse_exit_args<'a>(args: &'a str, ctx: &mut Ctx) -> (&'a str, u8, u8, Option<Transition>) {
     let args = unquote(args);
-    let (room, args) = args.split_once(',').unwrap_or((args, "0,0"));
+    let Some((room, coords)) = args.split_once(',') else {
+        let msg = "exit coordinates are missing".to_string();
+        ctx.report(Code::BadExitCoords, msg, args);
+        return (args, 0, 0, None);
+    };
     let room = unquote(room);
-    let (x, y) = args.split_once(',').unwrap_or(("0", "0"));
-    let x = x.trim_ascii();
-    let y = y.trim_ascii();
-    let x: u8 = x.parse().unwrap_or_default();
-    let y: u8 = y.parse().unwrap_or_default();
-    (room, x, y)
+    let Some((x, rest)) = coords.split_once(',') else {
+        let msg = "exit Y coordinate is missing".to_string();
+        ctx.report(Code::BadExitCoords, msg, coords.trim_ascii());
+        return (room, parse_coord(coords, ctx), 0, None);
+    };
+    let (y, fx) = match rest.split_once(',') {
+        Some((y, fx)) => (y, Some(parse_transition(fx, ctx))),
+        None => (rest, None),
+    };
+    (room, parse_coord(x, ctx), parse_coord(y, ctx), fx)
+}
+
+/// Parse the transition of the `exit` function, reporting unknown ones.
+fn parse_transition(fx: &str, ctx: &mut Ctx) -> Transition {
+    let fx = unquote(fx.trim_ascii());
+    let transition = Transition::parse(fx);
+    if let Transition::Other(_) = transition {
+        let msg = format!("unknown transition: {fx}");
+        ctx.report(Code::UnknownTransition, msg, fx);
+    }
+    transition
+}
+
+/// Parse a coordinate of the `exit` function, reporting invalid values.
+fn parse_coord(v: &str, ctx: &mut Ctx) -> u8 {
+    let v = v.trim_ascii();
+    match v.parse() {
+        Ok(v) => v,
+        Err(_) => {
+            let msg = format!("invalid exit coordinate: {v}");
+            ctx.report(Code::BadExitCoords, msg, v);
+            0
+        }
+    }
 }
 
 /// Remove double quotes around the text.
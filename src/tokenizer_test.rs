@@ -27,6 +27,7 @@ fn test_tokenizer_tags() {
     check("{ / pg }", vec![CloseTag(Pg)]);
 
     check("{br}{br}", vec![OpenTag(Br), OpenTag(Br)]);
+    check("{br} {br}", vec![OpenTag(Br), w(" "), OpenTag(Br)]);
     check(
         "oh{br}hi{br}mark",
         vec![w("oh"), OpenTag(Br), w("hi"), OpenTag(Br), w("mark")],
@@ -52,22 +53,38 @@ fn test_tokenizer_tags() {
     check("{clr 1}", vec![OpenTag(Eff(TextEffect::Color(2)))]);
 
     let expr = Expr::SimpleExpr(SimpleExpr::Var("hi".to_string()));
-    check("{say hi}", vec![OpenTag(Say(expr.clone()))]);
-    check("{ say  hi }", vec![OpenTag(Say(expr))]);
+    check("{say hi}", vec![OpenTag(Say(0, expr.clone()))]);
+    check("{ say  hi }", vec![OpenTag(Say(0, expr))]);
     let expr = Expr::SimpleExpr(SimpleExpr::Item("cat".to_string()));
-    check(r#"{say {item "cat"}}"#, vec![OpenTag(Say(expr))]);
+    check(r#"{say {item "cat"}}"#, vec![OpenTag(Say(0, expr))]);
 
     check(
         r#"{exit "hi,3,4"}"#,
-        vec![OpenTag(Exit("hi".to_string(), 3, 4))],
+        vec![OpenTag(Exit("hi".to_string(), 3, 4, None))],
     );
     check(
         r#"{exit "hi",3,4}"#,
-        vec![OpenTag(Exit("hi".to_string(), 3, 4))],
+        vec![OpenTag(Exit("hi".to_string(), 3, 4, None))],
     );
     check(
         r#"{exit "hi", 3, 4}"#,
-        vec![OpenTag(Exit("hi".to_string(), 3, 4))],
+        vec![OpenTag(Exit("hi".to_string(), 3, 4, None))],
+    );
+    check(r#"{tune "1"}"#, vec![OpenTag(Tune("1".to_string()))]);
+    check(r#"{blip "meow"}"#, vec![OpenTag(Blip("meow".to_string()))]);
+    let fx = Some(Transition::FadeW);
+    check(
+        r#"{exit "hi",3,4,"fade_w"}"#,
+        vec![OpenTag(Exit("hi".to_string(), 3, 4, fx.clone()))],
+    );
+    check(
+        r#"{exit "hi,3,4,fade_w"}"#,
+        vec![OpenTag(Exit("hi".to_string(), 3, 4, fx))],
+    );
+    let fx = Some(Transition::Other("zoom".to_string()));
+    check(
+        r#"{exit "hi", 3, 4, "zoom"}"#,
+        vec![OpenTag(Exit("hi".to_string(), 3, 4, fx))],
     );
 }
 
@@ -107,15 +124,441 @@ fn test_tokenizer_assignment() {
         vec![OpenTag(Set("a".to_string(), expr))],
     );
 
-    let a = SimpleExpr::Val(Val::I(14));
-    let b = SimpleExpr::Val(Val::I(15));
-    let expr = Expr::BinOp(BinOp::Add, a, b);
-    check("{say 14 + 15}", vec![OpenTag(Say(expr))]);
+    let expr = bin(BinOp::Add, int(14), int(15));
+    check("{say 14 + 15}", vec![OpenTag(Say(0, expr))]);
+
+    let expr = bin(BinOp::Add, int(-4), int(5));
+    check("{say -4+5}", vec![OpenTag(Say(0, expr))]);
+}
+
+#[test]
+fn test_tokenizer_expr() {
+    use BinOp::*;
+    check_expr("a", var("a"));
+    check_expr("(a)", var("a"));
+    check_expr("-a", Expr::Neg(Box::new(var("a"))));
+    check_expr("--3", Expr::Neg(Box::new(int(-3))));
+    check_expr("1 + 2 * 3", bin(Add, int(1), bin(Mul, int(2), int(3))));
+    check_expr("1 * 2 + 3", bin(Add, bin(Mul, int(1), int(2)), int(3)));
+    check_expr("(1 + 2) * 3", bin(Mul, bin(Add, int(1), int(2)), int(3)));
+    check_expr("1 - 2 - 3", bin(Sub, bin(Sub, int(1), int(2)), int(3)));
+    check_expr("8 / 4 / 2", bin(Div, bin(Div, int(8), int(4)), int(2)));
+    check_expr("1-2", bin(Sub, int(1), int(2)));
+    check_expr("1 - -2", bin(Sub, int(1), int(-2)));
+    check_expr(
+        "a + 1 >= b * 2",
+        bin(Gte, bin(Add, var("a"), int(1)), bin(Mul, var("b"), int(2))),
+    );
+    check_expr("a == b < c", bin(Lt, bin(Eq, var("a"), var("b")), var("c")));
+    check_expr("-(a + 1)", Expr::Neg(Box::new(bin(Add, var("a"), int(1)))));
+    let item = Expr::SimpleExpr(SimpleExpr::Item("red key".to_string()));
+    check_expr(r#"{item "red key"} * 2"#, bin(Mul, item, int(2)));
+    let s = |v: &str| Expr::SimpleExpr(SimpleExpr::Val(Val::S(v.to_string())));
+    check_expr(r#""a + b" + c"#, bin(Add, s("a + b"), var("c")));
+
+    let call = |name: &str, args| Expr::SimpleExpr(SimpleExpr::Call(name.to_string(), args));
+    check_expr("{rand} + 1", bin(Add, call("rand", vec![]), int(1)));
+    check_expr(
+        r#"{max a (b + 1) "x y" {item "key"}}"#,
+        call(
+            "max",
+            vec![
+                var("a"),
+                bin(Add, var("b"), int(1)),
+                s("x y"),
+                Expr::SimpleExpr(SimpleExpr::Item("key".to_string())),
+            ],
+        ),
+    );
+
+    // Expressions that cannot be parsed are strings.
+    check_expr("{1 2}", s("{1 2}"));
+    check_expr("(1 + 2", s("(1 + 2"));
+    check_expr("1 + ", s("1 +"));
+    check_expr("1 = 2", s("1 = 2"));
+    check_expr("hello world", s("hello world"));
+    check_expr("2cats", s("2cats"));
+}
+
+#[test]
+fn test_tokenizer_transition() {
+    let names = [
+        "fade_w", "fade_b", "wave", "tunnel", "slide_u", "slide_d", "slide_l", "slide_r", "zoom",
+    ];
+    for name in names {
+        assert_eq!(Transition::parse(name).as_str(), name);
+    }
+    assert_eq!(Transition::parse("slide_l"), Transition::SlideL);
+}
 
-    let a = SimpleExpr::Val(Val::I(-4));
-    let b = SimpleExpr::Val(Val::I(5));
-    let expr = Expr::BinOp(BinOp::Add, a, b);
-    check("{say -4+5}", vec![OpenTag(Say(expr))]);
+#[test]
+fn test_tokenizer_item() {
+    use Tag::*;
+    use Token::*;
+    let key = "key".to_string();
+    check(r#"{item "key"}"#, vec![OpenTag(Item(key.clone(), None))]);
+    check(
+        "{item key 3}",
+        vec![OpenTag(Item(key.clone(), Some(int(3))))],
+    );
+    let count = bin(BinOp::Add, item("key"), int(1));
+    check(
+        r#"{item "key" {item "key"} + 1}"#,
+        vec![OpenTag(Item(key.clone(), Some(count)))],
+    );
+    check(r#"{give "key"}"#, vec![OpenTag(Give(key.clone(), int(1)))]);
+    check(
+        r#"{take "key" n}"#,
+        vec![OpenTag(Take(key.clone(), var("n")))],
+    );
+    check(
+        r#"{give "big key" 2}"#,
+        vec![OpenTag(Give("big key".to_string(), int(2)))],
+    );
+}
+
+#[test]
+fn test_tokenizer_args() {
+    let s = |v: &str| Expr::SimpleExpr(SimpleExpr::Val(Val::S(v.to_string())));
+    assert_eq!(parse_args(""), vec![]);
+    assert_eq!(parse_args("  1   a "), vec![int(1), var("a")]);
+    assert_eq!(
+        parse_args(r#""hi there" 2cats"#),
+        vec![s("hi there"), s("2cats")]
+    );
+    assert_eq!(
+        parse_args("(1 + 2) -3"),
+        vec![bin(BinOp::Add, int(1), int(2)), int(-3)]
+    );
+}
+
+#[test]
+fn test_tokenizer_spanned() {
+    let text = "hi {wvy}there{/wvy}\n{ a = 1 + b }{say\n  \"é\"}{br}";
+    let tokens: Vec<_> = Tokenizer::new(text).spanned().collect();
+    let spans: Vec<_> = tokens.iter().map(|t| t.span.source(text)).collect();
+    let exp = vec![
+        "hi ",
+        "{wvy}",
+        "there",
+        "{/wvy}",
+        "\n",
+        "{ a = 1 + b }",
+        "{say\n  \"é\"}",
+        "{br}",
+    ];
+    assert_eq!(spans, exp);
+    let args: Vec<_> = tokens
+        .iter()
+        .map(|t| t.args.map(|a| a.source(text)))
+        .collect();
+    let exp = vec![
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some("1 + b"),
+        Some("\"é\""),
+        None,
+    ];
+    assert_eq!(args, exp);
+
+    let pos = |offset, line, col| Pos { offset, line, col };
+    assert_eq!(tokens[2].span.start, pos(8, 1, 9));
+    assert_eq!(tokens[4].span.end, pos(20, 2, 1));
+    assert_eq!(tokens[5].args.unwrap().start, pos(26, 2, 7));
+    let say = tokens[6].span;
+    assert_eq!(say.start, pos(33, 2, 14));
+    assert_eq!(say.end, pos(45, 3, 7));
+    assert_eq!(tokens[7].span.start, pos(45, 3, 7));
+
+    let tokens: Vec<_> = Tokenizer::new(text).spanned().map(|t| t.token).collect();
+    let exp: Vec<_> = Tokenizer::new(text).collect();
+    assert_eq!(tokens, exp);
+
+    let text = r#"{give "key" 2}{ take key }{item "key"}"#;
+    let tokens: Vec<_> = Tokenizer::new(text).spanned().collect();
+    let spans: Vec<_> = tokens.iter().map(|t| t.span.source(text)).collect();
+    assert_eq!(
+        spans,
+        vec![r#"{give "key" 2}"#, "{ take key }", r#"{item "key"}"#]
+    );
+    let args: Vec<_> = tokens
+        .iter()
+        .map(|t| t.args.map(|a| a.source(text)))
+        .collect();
+    assert_eq!(
+        args,
+        vec![Some(r#""key" 2"#), Some("key"), Some(r#""key""#)]
+    );
+}
+
+#[test]
+fn test_tokenizer_spanned_blocks() {
+    let text = "{cycle\n  - a {say x}\n  - {\n    - x > 1 ? {wvy}hi\n    - else ? yo\n    }\n}";
+    let tokens: Vec<_> = Tokenizer::new(text).spanned().collect();
+    assert_eq!(tokens.len(), 1);
+    let sources = |tokens: &[SpannedToken]| -> Vec<_> {
+        tokens.iter().map(|t| t.span.source(text)).collect()
+    };
+    let items = &tokens[0].items;
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].span.source(text), "a {say x}");
+    assert_eq!(items[0].cond, None);
+    assert_eq!(sources(&items[0].tokens), vec!["a ", "{say x}"]);
+    assert_eq!(items[0].tokens[1].args.unwrap().source(text), "x");
+
+    let branches = &items[1].tokens[0].items;
+    assert_eq!(branches.len(), 2);
+    assert_eq!(branches[0].cond.unwrap().source(text), "x > 1");
+    assert_eq!(sources(&branches[0].tokens), vec!["{wvy}", "hi"]);
+    assert_eq!(branches[1].cond, None);
+    assert_eq!(sources(&branches[1].tokens), vec!["yo"]);
+
+    let pos = |offset, line, col| Pos { offset, line, col };
+    assert_eq!(items[0].tokens[1].span.start, pos(13, 2, 7));
+    assert_eq!(branches[0].tokens[1].span.start, pos(46, 4, 20));
+    assert_eq!(branches[1].tokens[0].span.end, pos(64, 5, 16));
+
+    // Tokens of the items are the same as in the tag.
+    let Token::OpenTag(Tag::Seq(_, _, exp)) = &tokens[0].token else {
+        panic!("not a list block");
+    };
+    let items: Vec<Vec<_>> = items
+        .iter()
+        .map(|item| item.tokens.iter().map(|t| t.token.clone()).collect())
+        .collect();
+    assert_eq!(&items, exp);
+}
+
+#[test]
+fn test_tokenizer_diagnostics() {
+    use Code::*;
+    check_diag("hello {wvy}world{/wvy}{br}", vec![]);
+    check_diag("hello {wvy world", vec![(UnclosedTag, "{wvy world")]);
+    check_diag("hi {blegh} {say 1}", vec![(UnknownFunc, "blegh")]);
+    check_diag("{ blegh 1 2 }", vec![(UnknownFunc, "blegh")]);
+    check_diag(r#"{exit "hi",3,4}"#, vec![]);
+    check_diag(r#"{exit "hi"}"#, vec![(BadExitCoords, "hi")]);
+    check_diag(r#"{exit "hi",x,4}"#, vec![(BadExitCoords, "x")]);
+    check_diag(r#"{exit "hi,3,-4"}"#, vec![(BadExitCoords, "-4")]);
+    check_diag(r#"{exit "hi", 3}"#, vec![(BadExitCoords, "3")]);
+    check_diag(r#"{exit "hi",3,x,"wave"}"#, vec![(BadExitCoords, "x")]);
+    check_diag(r#"{exit "hi",3,4,"fade_w"}"#, vec![]);
+    check_diag(
+        r#"{exit "hi",3,4, "zoom"}"#,
+        vec![(UnknownTransition, "zoom")],
+    );
+    check_diag("{a = 1 =< 2}", vec![(InvalidOp, "=")]);
+    check_diag("{say a != 2}", vec![(InvalidOp, "!=")]);
+    check_diag("{a = hello world!}", vec![]);
+    check_diag("hi{/wvy}", vec![(UnmatchedClose, "{/wvy}")]);
+    check_diag("{wvy}hi{/rbw}{/wvy}", vec![(UnmatchedClose, "{/rbw}")]);
+    check_diag("{clr1}hi{/clr}", vec![]);
+    check_diag("{/br}", vec![(UnmatchedClose, "{/br}")]);
+    check_diag(r#"{item "key"}{give key}{take "key" 2}"#, vec![]);
+    check_diag(r#"{give "key" 1 =< 2}"#, vec![(InvalidOp, "=")]);
+    check_diag(r#"{take key 1 != 2}"#, vec![(InvalidOp, "!=")]);
+    check_diag(
+        "{cycle\n  - {nope}\n  - {- a =! 1 ? x}\n}",
+        vec![(UnknownFunc, "nope"), (InvalidOp, "=")],
+    );
+
+    let text = "hi\n  {say 1 = 2}";
+    let diagnostics = Tokenizer::new(text).spanned().diagnostics();
+    assert!(diagnostics.is_empty());
+    let mut tokenizer = Tokenizer::new(text).spanned();
+    tokenizer.by_ref().for_each(drop);
+    let diagnostics = tokenizer.diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    let diagnostic = &diagnostics[0];
+    assert_eq!(diagnostic.level, Level::Error);
+    assert_eq!(diagnostic.code.as_str(), "E004");
+    assert_eq!(diagnostic.message, "invalid operator: =");
+    let start = Pos {
+        offset: 12,
+        line: 2,
+        col: 10,
+    };
+    assert_eq!(diagnostic.span.start, start);
+}
+
+#[test]
+fn test_tokenizer_deep_nesting() {
+    // Too many nested braces don't overflow the depth counters.
+    let braces = alloc::format!("{}{}", "{".repeat(300), "}".repeat(300));
+    Tokenizer::new(&braces).for_each(drop);
+    Tokenizer::new(&alloc::format!("{{say {braces}}}")).for_each(drop);
+    Tokenizer::new(&alloc::format!("{{- a ? {braces}}}")).for_each(drop);
+}
+
+#[test]
+fn test_tokenizer_diagnostics_functions() {
+    let mut funcs = Functions::new();
+    funcs.register("hop", |_, _| Val::Undef.into());
+    let text = "{hop 1}{cycle\n - {hop}\n - {skip}\n}";
+    let mut tokenizer = Tokenizer::new(text).with_functions(&funcs);
+    tokenizer.by_ref().for_each(drop);
+    let diagnostics = tokenizer.diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].code, Code::UnknownFunc);
+    assert_eq!(diagnostics[0].span.source(text), "skip");
+}
+
+fn check_diag(given: &str, expected: Vec<(Code, &str)>) {
+    let mut tokenizer = Tokenizer::new(given);
+    tokenizer.by_ref().for_each(drop);
+    let actual: Vec<_> = tokenizer
+        .diagnostics()
+        .into_iter()
+        .map(|d| (d.code, d.span.source(given)))
+        .collect();
+    assert_eq!(actual, expected);
+}
+
+fn check_expr(given: &str, expected: Expr) {
+    let given = alloc::format!("{{say {given}}}");
+    check(&given, vec![Token::OpenTag(Tag::Say(0, expected))]);
+}
+
+fn bin(op: BinOp, lhs: Expr, rhs: Expr) -> Expr {
+    Expr::BinOp(op, Box::new(lhs), Box::new(rhs))
+}
+
+fn int(i: i16) -> Expr {
+    Expr::SimpleExpr(SimpleExpr::Val(Val::I(i)))
+}
+
+fn var(name: &str) -> Expr {
+    Expr::SimpleExpr(SimpleExpr::Var(name.to_string()))
+}
+
+fn item(name: &str) -> Expr {
+    Expr::SimpleExpr(SimpleExpr::Item(name.to_string()))
+}
+
+#[test]
+fn test_tokenizer_seq() {
+    use Tag::*;
+    use Token::*;
+    check(
+        "{sequence\n  - hello\n  - oh{br}hi\n}",
+        vec![OpenTag(Seq(
+            0,
+            SeqKind::Sequence,
+            vec![vec![w("hello")], vec![w("oh"), OpenTag(Br), w("hi")]],
+        ))],
+    );
+    check(
+        "{cycle - hi}",
+        vec![OpenTag(Seq(0, SeqKind::Cycle, vec![vec![w("hi")]]))],
+    );
+    check(
+        "{sequence\n  - one\n    two {br}  three\n}",
+        vec![OpenTag(Seq(
+            0,
+            SeqKind::Sequence,
+            vec![vec![
+                w("one"),
+                OpenTag(Br),
+                w("two "),
+                OpenTag(Br),
+                w("  three"),
+            ]],
+        ))],
+    );
+    check(
+        "{shuffle\n - a - b\n - c\n}",
+        vec![OpenTag(Seq(
+            0,
+            SeqKind::Shuffle,
+            vec![vec![w("a "), w("- "), w("b")], vec![w("c")]],
+        ))],
+    );
+    check(
+        "{cycle\n - {sequence\n  - a\n }\n}{cycle\n - b\n}",
+        vec![
+            OpenTag(Seq(
+                0,
+                SeqKind::Cycle,
+                vec![vec![OpenTag(Seq(1, SeqKind::Sequence, vec![vec![w("a")]]))]],
+            )),
+            OpenTag(Seq(2, SeqKind::Cycle, vec![vec![w("b")]])),
+        ],
+    );
+
+    // Block IDs don't overflow.
+    let tokens: Vec<_> = Tokenizer::nested("{cycle - a}{cycle - b}", u16::MAX).collect();
+    let ids: Vec<_> = tokens
+        .iter()
+        .map(|t| match t {
+            OpenTag(Seq(id, ..)) => *id,
+            _ => 0,
+        })
+        .collect();
+    assert_eq!(ids, vec![u16::MAX, u16::MAX]);
+}
+
+#[test]
+fn test_tokenizer_say_ids() {
+    use Tag::*;
+    use Token::*;
+    // Say tags share IDs with blocks.
+    let expr = Expr::SimpleExpr(SimpleExpr::Var("s".to_string()));
+    check(
+        "{cycle\n - a\n}{say s}",
+        vec![
+            OpenTag(Seq(0, SeqKind::Cycle, vec![vec![w("a")]])),
+            OpenTag(Say(1, expr)),
+        ],
+    );
+}
+
+#[test]
+fn test_tokenizer_if() {
+    use Tag::*;
+    use Token::*;
+    let item = Expr::SimpleExpr(SimpleExpr::Item("key".to_string()));
+    let cond = bin(BinOp::Gte, item, int(1));
+    check(
+        "{\n  - {item \"key\"} >= 1 ?\n    open{br}it\n  - else ?\n    nope\n}",
+        vec![OpenTag(If(vec![
+            (Some(cond), vec![w("open"), OpenTag(Br), w("it")]),
+            (None, vec![w("nope")]),
+        ]))],
+    );
+
+    // Lines of branches are trimmed, as they are indented by the editor.
+    let cond = Expr::SimpleExpr(SimpleExpr::Var("a".to_string()));
+    check(
+        "{\n  - a ?\n    line one\n    line two\n  - else ?\n    {cycle\n      - x\n        y\n    }\n}",
+        vec![OpenTag(If(vec![
+            (
+                Some(cond.clone()),
+                vec![w("line "), w("one"), OpenTag(Br), w("line "), w("two")],
+            ),
+            (
+                None,
+                vec![OpenTag(Seq(
+                    0,
+                    SeqKind::Cycle,
+                    vec![vec![w("x"), OpenTag(Br), w("y")]],
+                ))],
+            ),
+        ]))],
+    );
+
+    check(
+        "{- a ? what?\n - else ? {cycle - x}}",
+        vec![OpenTag(If(vec![
+            (Some(cond), vec![w("what?")]),
+            (
+                None,
+                vec![OpenTag(Seq(0, SeqKind::Cycle, vec![vec![w("x")]]))],
+            ),
+        ]))],
+    );
 }
 
 fn w(w: &str) -> Token {
@@ -1,6 +1,7 @@
 use crate::*;
 use alloc::string::String;
 use alloc::string::ToString;
+use core::fmt;
 use hashbrown::HashMap;
 
 #[derive(Debug, Default, Clone, PartialEq)]
@@ -29,6 +30,47 @@ impl Val {
         }
         Val::S(unquote(s).to_string())
     }
+
+    /// Convert the value into an integer.
+    ///
+    /// Floats are truncated, strings are parsed, and everything else is 0.
+    pub fn to_int(&self) -> i16 {
+        match self {
+            Val::Undef => 0,
+            Val::I(i) => *i,
+            Val::F(f) => *f as i16,
+            Val::S(s) => match Val::new(s) {
+                Val::I(i) => i,
+                Val::F(f) => f as i16,
+                _ => 0,
+            },
+        }
+    }
+
+    /// Check if the value is considered true when used as a condition.
+    ///
+    /// Like in Bitsy, which compares the value with `== true`, only values
+    /// equal to 1 are true. So 2, "0", and "false" are all false.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Val::Undef => false,
+            Val::I(i) => *i == 1,
+            Val::S(s) => s.trim_ascii().parse::<f32>() == Ok(1.),
+            Val::F(f) => *f == 1.,
+        }
+    }
+}
+
+/// The value as shown in dialogs. Undefined values are shown as 0.
+impl fmt::Display for Val {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Val::Undef => write!(f, "0"),
+            Val::I(i) => write!(f, "{i}"),
+            Val::S(s) => write!(f, "{s}"),
+            Val::F(v) => write!(f, "{v}"),
+        }
+    }
 }
 
 fn unquote(v: &str) -> &str {
@@ -58,7 +100,58 @@ impl Vars {
         self.items.insert(name, v);
     }
 
+    /// Set initial values of variables from the VAR sections of the game.
+    pub fn load(&mut self, vars: &[Variable]) {
+        for var in vars {
+            self.set(var.id.clone(), Val::new(&var.value));
+        }
+    }
+
+    /// Get the variable value. Missing variables are undefined.
     pub fn get(&self, name: &str) -> &Val {
         self.items.get(name).unwrap_or(&Val::Undef)
     }
+
+    /// Get the variable value converted into an integer.
+    pub fn get_int(&self, name: &str) -> i16 {
+        self.get(name).to_int()
+    }
+
+    /// Get the variable value converted into a string, as shown in dialogs.
+    ///
+    /// Missing variables are shown as "0", like other undefined values.
+    /// Use [`Vars::contains`] to tell them apart from variables set to 0.
+    pub fn get_str(&self, name: &str) -> String {
+        self.get(name).to_string()
+    }
+
+    /// Get the variable value converted into a bool, as used in conditions.
+    pub fn get_bool(&self, name: &str) -> bool {
+        self.get(name).is_truthy()
+    }
+
+    /// Check if the variable is set.
+    pub fn contains(&self, name: &str) -> bool {
+        self.items.contains_key(name)
+    }
+
+    /// Remove the variable. Returns its value if it was set.
+    pub fn remove(&mut self, name: &str) -> Option<Val> {
+        self.items.remove(name)
+    }
+
+    /// Iterate over all variables and their values, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Val)> {
+        self.items.iter()
+    }
+
+    /// The number of set variables.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Check if no variables are set.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
 }
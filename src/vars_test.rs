@@ -0,0 +1,72 @@
+use crate::*;
+
+#[test]
+fn test_val_coercion() {
+    assert_eq!(Val::Undef.to_int(), 0);
+    assert_eq!(Val::I(-4).to_int(), -4);
+    assert_eq!(Val::F(2.7).to_int(), 2);
+    assert_eq!(Val::S(" 12 ".to_string()).to_int(), 12);
+    assert_eq!(Val::S("3.5".to_string()).to_int(), 3);
+    assert_eq!(Val::S("true".to_string()).to_int(), 1);
+    assert_eq!(Val::S("cat".to_string()).to_int(), 0);
+
+    assert_eq!(Val::Undef.to_string(), "0");
+    assert_eq!(Val::I(7).to_string(), "7");
+    assert_eq!(Val::F(0.5).to_string(), "0.5");
+    assert_eq!(Val::S("hi".to_string()).to_string(), "hi");
+
+    assert!(!Val::Undef.is_truthy());
+    assert!(!Val::I(0).is_truthy());
+    assert!(Val::I(1).is_truthy());
+    assert!(!Val::I(2).is_truthy());
+    assert!(Val::F(1.).is_truthy());
+    assert!(!Val::F(0.1).is_truthy());
+    assert!(!Val::S("".to_string()).is_truthy());
+    assert!(!Val::S("0".to_string()).is_truthy());
+    assert!(!Val::S("false".to_string()).is_truthy());
+    assert!(!Val::S("true".to_string()).is_truthy());
+    assert!(Val::S(" 1 ".to_string()).is_truthy());
+}
+
+#[test]
+fn test_vars() {
+    let mut vars = Vars::new();
+    assert!(vars.is_empty());
+    assert!(!vars.contains("a"));
+    assert_eq!(vars.get("a"), &Val::Undef);
+    assert_eq!(vars.get_int("a"), 0);
+    assert_eq!(vars.get_str("a"), "0");
+    assert!(!vars.get_bool("a"));
+
+    vars.set("a".to_string(), Val::S("42".to_string()));
+    vars.set("b".to_string(), Val::I(1));
+    assert!(vars.contains("a"));
+    assert_eq!(vars.len(), 2);
+    assert_eq!(vars.get_int("a"), 42);
+    assert_eq!(vars.get_str("b"), "1");
+    assert!(vars.get_bool("b"));
+
+    let mut all: Vec<_> = vars.iter().collect();
+    all.sort_by_key(|(name, _)| name.as_str());
+    let (a, b) = ("a".to_string(), "b".to_string());
+    assert_eq!(all, vec![(&a, &Val::S("42".to_string())), (&b, &Val::I(1))]);
+
+    assert_eq!(vars.remove("b"), Some(Val::I(1)));
+    assert_eq!(vars.remove("b"), None);
+    assert!(!vars.contains("b"));
+    assert_eq!(vars.len(), 1);
+}
+
+#[test]
+fn test_vars_load() {
+    let var = |id: &str, value: &str| Variable {
+        id: id.to_string(),
+        value: value.to_string(),
+    };
+    let mut vars = Vars::new();
+    vars.set("a".to_string(), Val::I(1));
+    vars.load(&[var("a", "42"), var("b", "true"), var("c", "\"hi\"")]);
+    assert_eq!(vars.get("a"), &Val::I(42));
+    assert_eq!(vars.get("b"), &Val::I(1));
+    assert_eq!(vars.get("c"), &Val::S("hi".to_string()));
+}
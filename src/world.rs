@@ -0,0 +1,698 @@
+use crate::*;
+use alloc::format;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// Parsed Bitsy game data.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct World {
+    /// The game title. The first line of the game data.
+    pub title: String,
+    /// Bitsy version from the "# BITSY VERSION" comment.
+    pub version: Option<String>,
+    /// Flags, like ROOM_FORMAT or VER_MAJ.
+    pub flags: Vec<(String, String)>,
+    /// The name of the font used for dialogs. If not set, it's ascii_small.
+    pub default_font: Option<String>,
+    /// Text direction: LTR or RTL. If not set, it's LTR.
+    pub text_direction: Option<String>,
+    /// One-line settings, like "DEFAULT_FONT", that are not followed by an empty line.
+    ///
+    /// Bitsy always puts an empty line after them but hand-written games might not.
+    pub compact: Vec<String>,
+    pub palettes: Vec<Palette>,
+    pub rooms: Vec<Room>,
+    pub tiles: Vec<Tile>,
+    pub sprites: Vec<Sprite>,
+    pub items: Vec<Item>,
+    pub dialogs: Vec<Dialog>,
+    /// Ending texts. Used only by old versions of Bitsy,
+    /// newer ones store endings as dialogs.
+    pub endings: Vec<Dialog>,
+    pub variables: Vec<Variable>,
+    pub tunes: Vec<Tune>,
+    pub blips: Vec<Blip>,
+    /// Custom fonts embedded into the game data.
+    pub fonts: Vec<FontData>,
+    /// Comments and sections not supported by the parser.
+    pub unknown: Vec<RawSection>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub id: ID,
+    pub name: Option<String>,
+    /// RGB colors. The first one is background, the second is tiles,
+    /// the third is sprites. The rest are extra colors.
+    pub colors: Vec<[u8; 3]>,
+    /// Properties not supported by the parser.
+    pub extra: Vec<RawProp>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Room {
+    pub id: ID,
+    pub name: Option<String>,
+    /// Tile IDs, row by row. "0" is an empty tile.
+    pub tiles: Vec<Vec<ID>>,
+    /// IDs of tiles that are walls in this room. Used by old versions of Bitsy.
+    pub walls: Vec<ID>,
+    pub items: Vec<Placement>,
+    pub exits: Vec<Exit>,
+    pub endings: Vec<Placement>,
+    pub palette: Option<ID>,
+    /// The tune played in the room.
+    pub tune: Option<ID>,
+    /// Properties not supported by the parser.
+    pub extra: Vec<RawProp>,
+}
+
+/// An object placed in a room at the given coordinates.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Placement {
+    pub id: ID,
+    pub x: u8,
+    pub y: u8,
+}
+
+/// A tile in a room that moves the player to another room.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Exit {
+    pub x: u8,
+    pub y: u8,
+    /// The destination room.
+    pub room: ID,
+    pub to_x: u8,
+    pub to_y: u8,
+    /// The transition effect.
+    pub transition: Option<Transition>,
+    /// The dialog to show when exiting.
+    pub dialog: Option<ID>,
+    /// Options not supported by the parser, like "LOCK x", in the original order.
+    ///
+    /// They are written after the known ones.
+    pub extra: Vec<String>,
+}
+
+/// A drawing frame. Pixels are stored row by row, `true` is filled.
+pub type Frame = Vec<Vec<bool>>;
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Tile {
+    pub id: ID,
+    pub name: Option<String>,
+    /// Animation frames.
+    pub frames: Vec<Frame>,
+    /// If the player can't walk through the tile.
+    pub wall: Option<bool>,
+    /// Palette color index.
+    pub color: Option<u8>,
+    /// Properties not supported by the parser.
+    pub extra: Vec<RawProp>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Sprite {
+    pub id: ID,
+    pub name: Option<String>,
+    /// Animation frames.
+    pub frames: Vec<Frame>,
+    /// The dialog to show when the player bumps into the sprite.
+    pub dialog: Option<ID>,
+    /// The room and coordinates where the sprite is placed.
+    pub pos: Option<(ID, u8, u8)>,
+    /// Items the sprite starts with. Used for the player's avatar.
+    pub inventory: Vec<(ID, u16)>,
+    /// Palette color index.
+    pub color: Option<u8>,
+    /// The blip played when the sprite's dialog is shown.
+    pub blip: Option<ID>,
+    /// Properties not supported by the parser.
+    pub extra: Vec<RawProp>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Item {
+    pub id: ID,
+    pub name: Option<String>,
+    /// Animation frames.
+    pub frames: Vec<Frame>,
+    /// The dialog to show when the player picks up the item.
+    pub dialog: Option<ID>,
+    /// Palette color index.
+    pub color: Option<u8>,
+    /// The blip played when the item is picked up.
+    pub blip: Option<ID>,
+    /// Properties not supported by the parser.
+    pub extra: Vec<RawProp>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Dialog {
+    pub id: ID,
+    pub name: Option<String>,
+    /// The dialog script.
+    pub text: String,
+    /// If the single-line text is wrapped into triple quotes in the game data.
+    ///
+    /// Multiline texts are always wrapped.
+    pub quoted: bool,
+    /// Properties not supported by the parser.
+    pub extra: Vec<RawProp>,
+}
+
+impl Dialog {
+    /// Tokenize the dialog script.
+    pub fn tokens(&self) -> Tokenizer<'_> {
+        Tokenizer::new(&self.text)
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Variable {
+    pub id: ID,
+    /// The initial value, as written in the game data.
+    pub value: String,
+}
+
+/// A bar of a tune. Each line is a voice, like melody or harmony.
+///
+/// Voices are lists of notes, one per step, kept as written in the game data.
+/// "0" is a rest.
+pub type Bar = Vec<Vec<String>>;
+
+/// A music tune.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Tune {
+    pub id: ID,
+    pub name: Option<String>,
+    pub bars: Vec<Bar>,
+    /// Other properties, like KEY, TMP, SQR, or ARP, in the original order.
+    pub props: Vec<(String, String)>,
+}
+
+impl Tune {
+    /// Get the value of the property, like "TMP".
+    pub fn prop(&self, name: &str) -> Option<&str> {
+        find_prop(&self.props, name)
+    }
+}
+
+/// A sound effect played when a dialog is shown.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Blip {
+    pub id: ID,
+    pub name: Option<String>,
+    /// Notes played one after another, as written in the game data.
+    pub notes: Vec<String>,
+    /// Other properties, like ENV, BEAT, SQR, or RPT, in the original order.
+    pub props: Vec<(String, String)>,
+}
+
+impl Blip {
+    /// Get the value of the property, like "ENV".
+    pub fn prop(&self, name: &str) -> Option<&str> {
+        find_prop(&self.props, name)
+    }
+}
+
+/// A part of the game data that is kept as is.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct RawSection {
+    /// The header of the section after which this one goes, like "ROOM 0".
+    ///
+    /// Empty for sections right after the title, "!" for sections right
+    /// after the flags, and "# BITSY VERSION" for sections right after
+    /// the version comment.
+    pub after: String,
+    /// The raw text, including the trailing line breaks.
+    pub text: String,
+}
+
+/// A property line not supported by the parser, kept as is.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct RawProp {
+    /// How many known properties go before this one in the section.
+    pub after: usize,
+    /// The whole line, as written in the game data.
+    pub line: String,
+}
+
+/// Raw data of a font embedded into the game data.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct FontData {
+    pub name: String,
+    /// The whole font definition, including the FONT line.
+    pub data: String,
+}
+
+impl World {
+    /// Parse Bitsy game data.
+    ///
+    /// Invalid values are skipped or replaced by defaults.
+    pub fn parse(text: &str) -> Self {
+        let mut world = World::default();
+        let mut lines = text.lines().peekable();
+        world.title = parse_text(&mut lines).unwrap_or_default();
+        // The header of the last parsed section.
+        let mut after = String::new();
+        while let Some(line) = lines.next() {
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(version) = line.strip_prefix("# BITSY VERSION ") {
+                world.version = Some(version.trim_ascii().to_string());
+                after = "# BITSY VERSION".to_string();
+                continue;
+            }
+            if line.starts_with('#') {
+                let mut text = format!("{line}\n");
+                if lines.next_if_eq(&"").is_some() {
+                    text.push('\n');
+                }
+                let after = after.clone();
+                world.unknown.push(RawSection { after, text });
+                continue;
+            }
+            if let Some(flag) = line.strip_prefix("! ") {
+                let (name, val) = split_prop(flag);
+                world.flags.push((name.to_string(), val.to_string()));
+                after = "!".to_string();
+                continue;
+            }
+            let (kind, id) = split_prop(line);
+            let id = id.to_string();
+            let prev = core::mem::replace(&mut after, line.to_string());
+            if kind == "SET" {
+                after = format!("ROOM {id}");
+            }
+            if matches!(kind, "DEFAULT_FONT" | "TEXT_DIRECTION") && lines.peek() != Some(&"") {
+                world.compact.push(kind.to_string());
+            }
+            match kind {
+                "DEFAULT_FONT" => world.default_font = Some(id),
+                "TEXT_DIRECTION" => world.text_direction = Some(id),
+                "FONT" => world.fonts.push(parse_font(line, id, &mut lines)),
+                "PAL" => world.palettes.push(parse_palette(id, &mut lines)),
+                "ROOM" | "SET" => world.rooms.push(parse_room(id, &mut lines)),
+                "TIL" => world.tiles.push(parse_tile(id, &mut lines)),
+                "SPR" => world.sprites.push(parse_sprite(id, &mut lines)),
+                "ITM" => world.items.push(parse_item(id, &mut lines)),
+                "DLG" => world.dialogs.push(parse_dialog(id, &mut lines)),
+                "END" => world.endings.push(parse_dialog(id, &mut lines)),
+                "VAR" => world.variables.push(parse_variable(id, &mut lines)),
+                "TUNE" => world.tunes.push(parse_tune(id, &mut lines)),
+                "BLIP" => world.blips.push(parse_blip(id, &mut lines)),
+                _ => {
+                    let text = read_section(line, &mut lines);
+                    world.unknown.push(RawSection { after: prev, text });
+                    after = after_unknown(&world.unknown);
+                }
+            }
+        }
+        world
+    }
+
+    /// Get the value of the flag, like ROOM_FORMAT.
+    pub fn flag(&self, name: &str) -> Option<&str> {
+        let (_, val) = self.flags.iter().find(|(n, _)| n == name)?;
+        Some(val)
+    }
+
+    pub fn palette(&self, id: &str) -> Option<&Palette> {
+        self.palettes.iter().find(|p| p.id == id)
+    }
+
+    pub fn room(&self, id: &str) -> Option<&Room> {
+        self.rooms.iter().find(|r| r.id == id)
+    }
+
+    pub fn tile(&self, id: &str) -> Option<&Tile> {
+        self.tiles.iter().find(|t| t.id == id)
+    }
+
+    pub fn sprite(&self, id: &str) -> Option<&Sprite> {
+        self.sprites.iter().find(|s| s.id == id)
+    }
+
+    pub fn item(&self, id: &str) -> Option<&Item> {
+        self.items.iter().find(|i| i.id == id)
+    }
+
+    pub fn tune(&self, id: &str) -> Option<&Tune> {
+        self.tunes.iter().find(|t| t.id == id)
+    }
+
+    pub fn blip(&self, id: &str) -> Option<&Blip> {
+        self.blips.iter().find(|b| b.id == id)
+    }
+
+    /// Find a dialog by ID. Old-style endings are also dialogs.
+    pub fn dialog(&self, id: &str) -> Option<&Dialog> {
+        let mut dialogs = self.dialogs.iter().chain(self.endings.iter());
+        dialogs.find(|d| d.id == id)
+    }
+}
+
+type Lines<'a> = core::iter::Peekable<core::str::Lines<'a>>;
+
+/// Split the line into the property name and its value.
+fn split_prop(line: &str) -> (&str, &str) {
+    let (name, val) = line.split_once(' ').unwrap_or((line, ""));
+    (name, val.trim_ascii())
+}
+
+/// Parse "x,y" coordinates.
+fn parse_coords(v: &str) -> (u8, u8) {
+    let (x, y) = v.split_once(',').unwrap_or((v, ""));
+    let x = x.trim_ascii().parse().unwrap_or_default();
+    let y = y.trim_ascii().parse().unwrap_or_default();
+    (x, y)
+}
+
+/// Read a single line of text or a multiline text wrapped into triple quotes.
+fn parse_text(lines: &mut Lines) -> Option<String> {
+    let line = lines.next()?;
+    if line != r#"""""# {
+        return Some(line.to_string());
+    }
+    let mut text = String::new();
+    for line in lines.by_ref() {
+        if line == r#"""""# {
+            break;
+        }
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(line);
+    }
+    Some(text)
+}
+
+/// Keep the unknown property line.
+///
+/// The index is the index of the line among all property lines of the section.
+fn push_extra(extra: &mut Vec<RawProp>, index: usize, line: &str) {
+    let after = index - extra.len();
+    let line = line.to_string();
+    extra.push(RawProp { after, line });
+}
+
+/// Iterate over the lines of the current section until an empty line.
+fn section<'a>(lines: &mut Lines<'a>) -> impl Iterator<Item = &'a str> {
+    lines.by_ref().take_while(|line| !line.is_empty())
+}
+
+/// Read the raw text of an unsupported section.
+fn read_section(header: &str, lines: &mut Lines) -> String {
+    let mut text = format!("{header}\n");
+    for line in section(lines) {
+        text.push_str(line);
+        text.push('\n');
+    }
+    text.push('\n');
+    text
+}
+
+/// Get the anchor for sections that go after an unknown section.
+///
+/// Unknown sections don't have headers that the serializer can find,
+/// so sections going after them are anchored to the same place.
+fn after_unknown(unknown: &[RawSection]) -> String {
+    match unknown.last() {
+        Some(raw) => raw.after.clone(),
+        None => String::new(),
+    }
+}
+
+fn parse_palette(id: ID, lines: &mut Lines) -> Palette {
+    let mut pal = Palette {
+        id,
+        ..Palette::default()
+    };
+    for (i, line) in section(lines).enumerate() {
+        if let Some(name) = line.strip_prefix("NAME ") {
+            pal.name = Some(name.to_string());
+            continue;
+        }
+        if line.contains(' ') {
+            push_extra(&mut pal.extra, i, line);
+            continue;
+        }
+        let mut color = [0; 3];
+        for (c, v) in color.iter_mut().zip(line.split(',')) {
+            *c = v.trim_ascii().parse().unwrap_or_default();
+        }
+        pal.colors.push(color);
+    }
+    pal
+}
+
+fn parse_room(id: ID, lines: &mut Lines) -> Room {
+    let mut room = Room {
+        id,
+        ..Room::default()
+    };
+    let mut index = 0;
+    for line in section(lines) {
+        if !line.contains(' ') {
+            let row = if line.contains(',') {
+                line.split(',').map(ToString::to_string).collect()
+            } else {
+                line.chars().map(|ch| ch.to_string()).collect()
+            };
+            room.tiles.push(row);
+            continue;
+        }
+        let i = index;
+        index += 1;
+        let (name, val) = split_prop(line);
+        match name {
+            "NAME" => room.name = Some(val.to_string()),
+            "WAL" => room.walls = val.split(',').map(ToString::to_string).collect(),
+            "ITM" => room.items.push(parse_placement(val)),
+            "END" => room.endings.push(parse_placement(val)),
+            "EXT" => room.exits.push(parse_exit(val)),
+            "PAL" => room.palette = Some(val.to_string()),
+            "TUNE" => room.tune = Some(val.to_string()),
+            _ => push_extra(&mut room.extra, i, line),
+        }
+    }
+    room
+}
+
+/// Parse "id x,y".
+fn parse_placement(val: &str) -> Placement {
+    let (id, coords) = split_prop(val);
+    let (x, y) = parse_coords(coords);
+    let id = id.to_string();
+    Placement { id, x, y }
+}
+
+/// Parse "x,y room x,y FX effect DLG id".
+fn parse_exit(val: &str) -> Exit {
+    let mut parts = val.split(' ');
+    let (x, y) = parse_coords(parts.next().unwrap_or_default());
+    let room = parts.next().unwrap_or_default().to_string();
+    let (to_x, to_y) = parse_coords(parts.next().unwrap_or_default());
+    let mut exit = Exit {
+        x,
+        y,
+        room,
+        to_x,
+        to_y,
+        ..Exit::default()
+    };
+    while let Some(part) = parts.next() {
+        let val = parts.next().map(ToString::to_string);
+        match part {
+            "FX" => exit.transition = val.as_deref().map(Transition::parse),
+            "DLG" => exit.dialog = val,
+            _ => match val {
+                Some(val) => exit.extra.push(format!("{part} {val}")),
+                None => exit.extra.push(part.to_string()),
+            },
+        }
+    }
+    exit
+}
+
+/// Parse animation frames of a drawing and return the rest of the lines.
+fn parse_frames<'a>(lines: &mut Lines<'a>) -> (Vec<Frame>, Vec<&'a str>) {
+    let mut frames = Vec::new();
+    let mut frame = Frame::new();
+    let mut props = Vec::new();
+    for line in section(lines) {
+        if line == ">" {
+            frames.push(core::mem::take(&mut frame));
+        } else if line.chars().all(|ch| ch == '0' || ch == '1') {
+            frame.push(line.chars().map(|ch| ch == '1').collect());
+        } else {
+            props.push(line);
+        }
+    }
+    if !frame.is_empty() {
+        frames.push(frame);
+    }
+    (frames, props)
+}
+
+fn parse_tile(id: ID, lines: &mut Lines) -> Tile {
+    let (frames, props) = parse_frames(lines);
+    let mut tile = Tile {
+        id,
+        frames,
+        ..Tile::default()
+    };
+    for (i, line) in props.into_iter().enumerate() {
+        let (name, val) = split_prop(line);
+        match name {
+            "NAME" => tile.name = Some(val.to_string()),
+            "WAL" => tile.wall = Some(val == "true"),
+            "COL" => tile.color = val.parse().ok(),
+            _ => push_extra(&mut tile.extra, i, line),
+        }
+    }
+    tile
+}
+
+fn parse_sprite(id: ID, lines: &mut Lines) -> Sprite {
+    let (frames, props) = parse_frames(lines);
+    let mut sprite = Sprite {
+        id,
+        frames,
+        ..Sprite::default()
+    };
+    for (i, line) in props.into_iter().enumerate() {
+        let (name, val) = split_prop(line);
+        match name {
+            "NAME" => sprite.name = Some(val.to_string()),
+            "DLG" => sprite.dialog = Some(val.to_string()),
+            "POS" => {
+                let place = parse_placement(val);
+                sprite.pos = Some((place.id, place.x, place.y));
+            }
+            "ITM" => {
+                let (item, count) = split_prop(val);
+                let count = count.parse().unwrap_or_default();
+                sprite.inventory.push((item.to_string(), count));
+            }
+            "COL" => sprite.color = val.parse().ok(),
+            "BLIP" => sprite.blip = Some(val.to_string()),
+            _ => push_extra(&mut sprite.extra, i, line),
+        }
+    }
+    sprite
+}
+
+fn parse_item(id: ID, lines: &mut Lines) -> Item {
+    let (frames, props) = parse_frames(lines);
+    let mut item = Item {
+        id,
+        frames,
+        ..Item::default()
+    };
+    for (i, line) in props.into_iter().enumerate() {
+        let (name, val) = split_prop(line);
+        match name {
+            "NAME" => item.name = Some(val.to_string()),
+            "DLG" => item.dialog = Some(val.to_string()),
+            "COL" => item.color = val.parse().ok(),
+            "BLIP" => item.blip = Some(val.to_string()),
+            _ => push_extra(&mut item.extra, i, line),
+        }
+    }
+    item
+}
+
+fn parse_dialog(id: ID, lines: &mut Lines) -> Dialog {
+    let quoted = lines.peek() == Some(&r#"""""#);
+    let text = parse_text(lines).unwrap_or_default();
+    let quoted = quoted && !text.contains('\n');
+    let mut dialog = Dialog {
+        id,
+        text,
+        quoted,
+        ..Dialog::default()
+    };
+    if dialog.text.is_empty() {
+        return dialog;
+    }
+    for (i, line) in section(lines).enumerate() {
+        match line.strip_prefix("NAME ") {
+            Some(name) => dialog.name = Some(name.to_string()),
+            None => push_extra(&mut dialog.extra, i, line),
+        }
+    }
+    dialog
+}
+
+fn parse_variable(id: ID, lines: &mut Lines) -> Variable {
+    let mut section = section(lines);
+    let value = section.next().unwrap_or_default().to_string();
+    section.for_each(drop);
+    Variable { id, value }
+}
+
+fn parse_tune(id: ID, lines: &mut Lines) -> Tune {
+    let mut tune = Tune {
+        id,
+        ..Tune::default()
+    };
+    let mut bar = Bar::new();
+    for line in section(lines) {
+        if line == ">" {
+            tune.bars.push(core::mem::take(&mut bar));
+            continue;
+        }
+        if !line.contains(' ') {
+            bar.push(line.split(',').map(ToString::to_string).collect());
+            continue;
+        }
+        let (name, val) = split_prop(line);
+        match name {
+            "NAME" => tune.name = Some(val.to_string()),
+            _ => tune.props.push((name.to_string(), val.to_string())),
+        }
+    }
+    if !bar.is_empty() {
+        tune.bars.push(bar);
+    }
+    tune
+}
+
+fn parse_blip(id: ID, lines: &mut Lines) -> Blip {
+    let mut blip = Blip {
+        id,
+        ..Blip::default()
+    };
+    let mut section = section(lines);
+    if let Some(notes) = section.next() {
+        blip.notes = notes.split(',').map(ToString::to_string).collect();
+    }
+    for line in section {
+        let (name, val) = split_prop(line);
+        match name {
+            "NAME" => blip.name = Some(val.to_string()),
+            _ => blip.props.push((name.to_string(), val.to_string())),
+        }
+    }
+    blip
+}
+
+fn find_prop<'a>(props: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    let (_, val) = props.iter().find(|(n, _)| n == name)?;
+    Some(val)
+}
+
+/// Read the font data. It takes all lines until the end of the game data.
+fn parse_font(header: &str, name: String, lines: &mut Lines) -> FontData {
+    let mut data = header.to_string();
+    for line in lines.by_ref() {
+        data.push('\n');
+        data.push_str(line);
+    }
+    let data = data.trim_ascii_end().to_string();
+    FontData { name, data }
+}
@@ -0,0 +1,388 @@
+use crate::*;
+
+const GAME: &str = r#"Write your game's title here
+
+# BITSY VERSION 8.12
+
+! VER_MAJ 8
+! VER_MIN 12
+! ROOM_FORMAT 1
+
+DEFAULT_FONT ascii_small
+TEXT_DIRECTION LTR
+
+PAL 0
+NAME blueprint
+0,82,204
+128,159,255
+255,255,255
+
+ROOM 0
+0,0,0,0
+0,a,a,0
+0,a,0,0
+0,0,0,0
+NAME example room
+ITM 0 2,1
+EXT 3,3 1 0,0 FX fade_w DLG 2
+END 3 1,2
+PAL 0
+TUNE 1
+
+ROOM 1
+0,0
+0,0
+
+TIL a
+11111111
+10000001
+10000001
+10011001
+10011001
+10000001
+10000001
+11111111
+NAME block
+WAL true
+
+SPR A
+00011000
+00011000
+00011000
+00111100
+01111110
+10111101
+00100100
+00100100
+POS 0 1,1
+ITM 0 2
+
+SPR a
+00000000
+00000000
+01010001
+01110001
+01110010
+01111100
+00111100
+00100100
+>
+00000000
+01010001
+01110001
+01110010
+01111100
+00111100
+00100100
+00000000
+NAME cat
+DLG 0
+POS 0 2,2
+COL 2
+BLIP 1
+
+ITM 0
+00000000
+00000000
+00000000
+00111100
+01100100
+00100100
+00011000
+00000000
+NAME tea
+DLG 1
+BLIP 1
+
+DLG 0
+I'm a cat
+NAME cat dialog
+
+DLG 1
+"""
+You found a nice warm cup of tea
+{sequence
+  - first
+  - second
+}
+"""
+NAME tea dialog
+
+DLG 2
+goodbye
+
+DLG 3
+the end
+
+VAR a
+42
+
+TUNE 1
+0,0,C4,0,E4,0,0,0,G4,0,0,0,0,0,0,0
+4C3,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0
+>
+2C5,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0
+NAME song
+KEY C,D,E,F,G,A,B d,g
+TMP MED
+SQR P2 P4
+
+BLIP 1
+E5,B5,B5
+NAME meow
+ENV 40 99 4 185 138
+BEAT 0 0
+SQR P2
+
+"#;
+
+#[test]
+fn test_world_parse() {
+    let world = World::parse(GAME);
+    assert_eq!(world.title, "Write your game's title here");
+    assert_eq!(world.version.as_deref(), Some("8.12"));
+    assert_eq!(world.flag("ROOM_FORMAT"), Some("1"));
+    assert_eq!(world.flag("NOPE"), None);
+    assert_eq!(world.default_font.as_deref(), Some("ascii_small"));
+    assert_eq!(world.text_direction.as_deref(), Some("LTR"));
+
+    let pal = world.palette("0").unwrap();
+    assert_eq!(pal.name.as_deref(), Some("blueprint"));
+    assert_eq!(
+        pal.colors,
+        vec![[0, 82, 204], [128, 159, 255], [255, 255, 255]]
+    );
+
+    assert_eq!(world.rooms.len(), 2);
+    let room = world.room("0").unwrap();
+    assert_eq!(room.name.as_deref(), Some("example room"));
+    assert_eq!(room.tiles.len(), 4);
+    assert_eq!(room.tiles[1], vec!["0", "a", "a", "0"]);
+    let item = Placement {
+        id: "0".to_string(),
+        x: 2,
+        y: 1,
+    };
+    assert_eq!(room.items, vec![item]);
+    let exit = Exit {
+        x: 3,
+        y: 3,
+        room: "1".to_string(),
+        to_x: 0,
+        to_y: 0,
+        transition: Some(Transition::FadeW),
+        dialog: Some("2".to_string()),
+        extra: Vec::new(),
+    };
+    assert_eq!(room.exits, vec![exit]);
+    assert_eq!(room.endings[0].id, "3");
+    assert_eq!(room.palette.as_deref(), Some("0"));
+    assert_eq!(room.tune.as_deref(), Some("1"));
+    let room = world.room("1").unwrap();
+    assert_eq!(room.tiles, vec![vec!["0", "0"], vec!["0", "0"]]);
+    assert_eq!(room.palette, None);
+    assert_eq!(room.tune, None);
+
+    let tile = world.tile("a").unwrap();
+    assert_eq!(tile.name.as_deref(), Some("block"));
+    assert_eq!(tile.wall, Some(true));
+    assert_eq!(tile.frames.len(), 1);
+    assert_eq!(tile.frames[0].len(), 8);
+    assert!(tile.frames[0][0].iter().all(|p| *p));
+    assert_eq!(tile.frames[0][1].iter().filter(|p| **p).count(), 2);
+
+    let avatar = world.sprite("A").unwrap();
+    assert_eq!(avatar.pos, Some(("0".to_string(), 1, 1)));
+    assert_eq!(avatar.inventory, vec![("0".to_string(), 2)]);
+    let cat = world.sprite("a").unwrap();
+    assert_eq!(cat.frames.len(), 2);
+    assert_eq!(cat.name.as_deref(), Some("cat"));
+    assert_eq!(cat.dialog.as_deref(), Some("0"));
+    assert_eq!(cat.color, Some(2));
+    assert_eq!(cat.blip.as_deref(), Some("1"));
+    assert_eq!(avatar.blip, None);
+
+    let tea = world.item("0").unwrap();
+    assert_eq!(tea.name.as_deref(), Some("tea"));
+    assert_eq!(tea.dialog.as_deref(), Some("1"));
+    assert_eq!(tea.blip.as_deref(), Some("1"));
+
+    assert_eq!(world.dialogs.len(), 4);
+    let dialog = world.dialog("0").unwrap();
+    assert_eq!(dialog.text, "I'm a cat");
+    assert_eq!(dialog.name.as_deref(), Some("cat dialog"));
+    let dialog = world.dialog("1").unwrap();
+    let text = "You found a nice warm cup of tea\n{sequence\n  - first\n  - second\n}";
+    assert_eq!(dialog.text, text);
+    assert_eq!(dialog.name.as_deref(), Some("tea dialog"));
+    assert_eq!(world.dialog("3").unwrap().text, "the end");
+
+    assert_eq!(world.variables.len(), 1);
+    assert_eq!(world.variables[0].id, "a");
+    assert_eq!(world.variables[0].value, "42");
+}
+
+#[test]
+fn test_world_dialog() {
+    let world = World::parse(GAME);
+    let dialog = world.dialog("1").unwrap();
+    let mut state = State {
+        dialog: dialog.id.clone(),
+        ..State::default()
+    };
+    let words: Vec<_> = Interpreter::new(dialog.tokens(), &mut state).collect();
+    assert_eq!(words.len(), 10);
+    assert_eq!(words[8], Word::LineBreak);
+    assert_eq!(
+        words[9],
+        Word::Text("first".to_string(), Effects::default())
+    );
+}
+
+#[test]
+fn test_world_old_format() {
+    let game = "old game\n\nROOM 0\n0a\na0\nWAL a\n\nEND 0\nbye\n\nFONT tiny\n\nSIZE 3 5\n\nCHAR 65\n010\n";
+    let world = World::parse(game);
+    assert_eq!(world.title, "old game");
+    let room = world.room("0").unwrap();
+    assert_eq!(room.tiles, vec![vec!["0", "a"], vec!["a", "0"]]);
+    assert_eq!(room.walls, vec!["a"]);
+    assert_eq!(world.dialog("0").unwrap().text, "bye");
+    assert_eq!(world.fonts.len(), 1);
+    assert_eq!(world.fonts[0].name, "tiny");
+    assert_eq!(world.fonts[0].data, "FONT tiny\n\nSIZE 3 5\n\nCHAR 65\n010");
+}
+
+#[test]
+fn test_world_sound() {
+    let world = World::parse(GAME);
+    assert_eq!(world.tunes.len(), 1);
+    let tune = world.tune("1").unwrap();
+    assert_eq!(tune.name.as_deref(), Some("song"));
+    assert_eq!(tune.bars.len(), 2);
+    assert_eq!(tune.bars[0].len(), 2);
+    assert_eq!(tune.bars[0][0].len(), 16);
+    assert_eq!(tune.bars[0][0][2], "C4");
+    assert_eq!(tune.bars[0][1][0], "4C3");
+    assert_eq!(tune.bars[1], vec![tune.bars[1][0].clone()]);
+    assert_eq!(tune.bars[1][0][0], "2C5");
+    assert_eq!(tune.prop("TMP"), Some("MED"));
+    assert_eq!(tune.prop("SQR"), Some("P2 P4"));
+    assert_eq!(tune.prop("ARP"), None);
+
+    let blip = world.blip("1").unwrap();
+    assert_eq!(blip.name.as_deref(), Some("meow"));
+    assert_eq!(blip.notes, vec!["E5", "B5", "B5"]);
+    assert_eq!(blip.prop("ENV"), Some("40 99 4 185 138"));
+    assert_eq!(world.blip("2"), None);
+}
+
+#[test]
+fn test_world_tune_voices() {
+    let game = "game\n\nTUNE 1\nC4,0,E4,0\nC3,0,0,G3\n>\n2D4,0,F4,0\n4D3,0,0,0\nNAME duet\n\n";
+    let world = World::parse(game);
+    let tune = world.tune("1").unwrap();
+    assert_eq!(tune.bars.len(), 2);
+    assert_eq!(
+        tune.bars[0],
+        vec![vec!["C4", "0", "E4", "0"], vec!["C3", "0", "0", "G3"]]
+    );
+    assert_eq!(
+        tune.bars[1],
+        vec![vec!["2D4", "0", "F4", "0"], vec!["4D3", "0", "0", "0"]]
+    );
+    assert_eq!(world.to_string(), game);
+}
+
+#[test]
+fn test_world_serialize() {
+    let world = World::parse(GAME);
+    assert_eq!(world.to_string(), GAME);
+
+    // Unknown sections and comments are preserved.
+    let game = GAME.replace("TIL a\n", "# walls\n\nFOO 1\n0,0\n\nTIL a\n");
+    let game = game.replace("DLG 0\nI'm", "# hello\nDLG 0\nI'm");
+    let world = World::parse(&game);
+    assert_eq!(world.unknown.len(), 3);
+    assert_eq!(world.unknown[1].text, "FOO 1\n0,0\n\n");
+    assert_eq!(world.to_string(), game);
+
+    // Unknown sections are kept when the section before them is removed.
+    let mut world = World::parse(&game);
+    world.rooms.pop();
+    let game = game.replace("ROOM 1\n0,0\n0,0\n\n", "");
+    let game = game.replace("# walls\n\nFOO 1\n0,0\n\n", "");
+    let game = game.replace("SQR P2\n\n", "SQR P2\n\n# walls\n\nFOO 1\n0,0\n\n");
+    assert_eq!(world.to_string(), game);
+}
+
+#[test]
+fn test_world_serialize_v8() {
+    let game = include_str!("testdata/default_v8.bitsy");
+    let world = World::parse(game);
+    assert_eq!(world.items[1].blip.as_deref(), Some("2"));
+    assert_eq!(world.blips.len(), 2);
+    assert_eq!(world.to_string(), game);
+
+    // Properties not supported by the parser are preserved.
+    let game = game.replace("TUNE 1\n\nTIL", "TUNE 1\nFOO 1 2\n\nTIL");
+    let game = game.replace("NAME block\n", "NAME block\nBAR baz\n");
+    let game = game.replace("POS 0 4,4\n", "POS 0 4,4\nFOO\n");
+    let game = game.replace("BLIP 2\n\nDLG", "BLIP 2\nFOO bar\n\nDLG");
+    let world = World::parse(&game);
+    let raw = |after, line: &str| RawProp {
+        after,
+        line: line.to_string(),
+    };
+    assert_eq!(world.rooms[0].extra, vec![raw(3, "FOO 1 2")]);
+    assert_eq!(world.tiles[0].extra, vec![raw(1, "BAR baz")]);
+    assert_eq!(world.sprites[0].extra, vec![raw(1, "FOO")]);
+    assert_eq!(world.items[1].extra, vec![raw(3, "FOO bar")]);
+    assert_eq!(world.to_string(), game);
+
+    // Unknown properties stay in place between the known ones.
+    let game = game.replace(
+        "NAME example room\nPAL 0\n",
+        "NAME example room\nBAZ 1\nPAL 0\n",
+    );
+    let game = game.replace("NAME cat\nDLG 0\n", "NAME cat\nQUX\nDLG 0\n");
+    let world = World::parse(&game);
+    assert_eq!(world.rooms[0].extra[0], raw(1, "BAZ 1"));
+    assert_eq!(world.to_string(), game);
+}
+
+#[test]
+fn test_world_serialize_raw_lines() {
+    // Unknown lines in palettes, dialogs, exits, and blips are kept.
+    let game = GAME.replace("NAME blueprint\n", "NAME blueprint\nFOO 1\n");
+    let game = game.replace("NAME cat dialog\n", "FOO bar\nNAME cat dialog\n");
+    let game = game.replace("fade_w DLG 2\n", "fade_w DLG 2 LOCK 1\n");
+    let game = game.replace("SQR P2\n", "SQR P2\nFOO\n");
+    let world = World::parse(&game);
+    assert_eq!(world.palettes[0].extra[0].line, "FOO 1");
+    assert_eq!(world.palettes[0].colors.len(), 3);
+    assert_eq!(world.dialogs[0].extra[0].after, 0);
+    assert_eq!(world.rooms[0].exits[0].extra, vec!["LOCK 1"]);
+    assert_eq!(world.to_string(), game);
+
+    // Single-line texts wrapped into triple quotes keep the quotes.
+    let game = GAME.replace("DLG 0\nI'm a cat", "DLG 0\n\"\"\"\nI'm a cat\n\"\"\"");
+    let world = World::parse(&game);
+    assert!(world.dialogs[0].quoted);
+    assert_eq!(world.dialogs[0].text, "I'm a cat");
+    assert_eq!(world.to_string(), game);
+}
+
+#[test]
+fn test_world_serialize_edited() {
+    let mut world = World::parse(GAME);
+    world.items[0].name = Some("coffee".to_string());
+    world.dialogs[0].text = "meow\nmeow".to_string();
+    world.flags[2].1 = "0".to_string();
+    let text = world.to_string();
+    assert!(text.contains("\nNAME coffee\n"));
+    assert!(text.contains("\nDLG 0\n\"\"\"\nmeow\nmeow\n\"\"\"\nNAME cat dialog\n"));
+    assert!(text.contains("\nROOM 1\n00\n00\n"));
+    let parsed = World::parse(&text);
+    assert_eq!(parsed, world);
+}
@@ -0,0 +1,344 @@
+use crate::*;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Serialize the game data into the Bitsy text format.
+///
+/// Sections are written in the same order and format as Bitsy does it.
+/// Comments and unknown sections are written as is, after the same section
+/// they were found after when parsing.
+impl fmt::Display for World {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut w = Writer {
+            f,
+            world: self,
+            written: Vec::new(),
+        };
+        write_text(w.f, &self.title, false)?;
+        w.f.write_str("\n")?;
+        w.raw_after(String::new())?;
+        if let Some(version) = &self.version {
+            writeln!(w.f, "# BITSY VERSION {version}")?;
+            w.f.write_str("\n")?;
+            w.raw_after("# BITSY VERSION".into())?;
+        }
+        if !self.flags.is_empty() {
+            for (name, val) in &self.flags {
+                writeln!(w.f, "! {name} {val}")?;
+            }
+            w.f.write_str("\n")?;
+            w.raw_after("!".into())?;
+        }
+        if let Some(font) = &self.default_font {
+            w.setting("DEFAULT_FONT", font)?;
+        }
+        if let Some(dir) = &self.text_direction {
+            w.setting("TEXT_DIRECTION", dir)?;
+        }
+        for pal in &self.palettes {
+            w.header(format!("PAL {}", pal.id), |f| write_palette(f, pal))?;
+        }
+        let commas = self.flag("ROOM_FORMAT") != Some("0");
+        for room in &self.rooms {
+            w.header(format!("ROOM {}", room.id), |f| write_room(f, room, commas))?;
+        }
+        for tile in &self.tiles {
+            w.header(format!("TIL {}", tile.id), |f| write_tile(f, tile))?;
+        }
+        for sprite in &self.sprites {
+            w.header(format!("SPR {}", sprite.id), |f| write_sprite(f, sprite))?;
+        }
+        for item in &self.items {
+            w.header(format!("ITM {}", item.id), |f| write_item(f, item))?;
+        }
+        for dialog in &self.dialogs {
+            w.header(format!("DLG {}", dialog.id), |f| write_dialog(f, dialog))?;
+        }
+        for ending in &self.endings {
+            w.header(format!("END {}", ending.id), |f| write_dialog(f, ending))?;
+        }
+        for var in &self.variables {
+            w.header(format!("VAR {}", var.id), |f| writeln!(f, "{}", var.value))?;
+        }
+        for tune in &self.tunes {
+            w.header(format!("TUNE {}", tune.id), |f| write_tune(f, tune))?;
+        }
+        for blip in &self.blips {
+            w.header(format!("BLIP {}", blip.id), |f| write_blip(f, blip))?;
+        }
+        w.raw_orphans()?;
+        for font in &self.fonts {
+            writeln!(w.f, "{}", font.data)?;
+        }
+        Ok(())
+    }
+}
+
+struct Writer<'a, 'b> {
+    f: &'a mut fmt::Formatter<'b>,
+    world: &'a World,
+    /// Headers of the sections written so far.
+    written: Vec<String>,
+}
+
+impl Writer<'_, '_> {
+    /// Write a section with the given header and body, followed by an empty line.
+    fn header<F>(&mut self, header: String, body: F) -> fmt::Result
+    where
+        F: FnOnce(&mut fmt::Formatter<'_>) -> fmt::Result,
+    {
+        writeln!(self.f, "{header}")?;
+        body(self.f)?;
+        self.f.write_str("\n")?;
+        self.raw_after(header)
+    }
+
+    /// Write a one-line setting, like DEFAULT_FONT.
+    fn setting(&mut self, kind: &str, val: &str) -> fmt::Result {
+        let header = format!("{kind} {val}");
+        if !self.world.compact.iter().any(|c| c == kind) {
+            return self.header(header, |_| Ok(()));
+        }
+        writeln!(self.f, "{header}")?;
+        self.raw_after(header)
+    }
+
+    /// Write raw sections that go after the given section.
+    fn raw_after(&mut self, header: String) -> fmt::Result {
+        for raw in &self.world.unknown {
+            if raw.after == header {
+                self.f.write_str(&raw.text)?;
+            }
+        }
+        self.written.push(header);
+        Ok(())
+    }
+
+    /// Write raw sections which sections they go after were removed.
+    fn raw_orphans(&mut self) -> fmt::Result {
+        for raw in &self.world.unknown {
+            if !self.written.contains(&raw.after) {
+                self.f.write_str(&raw.text)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes property lines of a section, putting unknown properties back
+/// between the known ones, at their original positions.
+struct Props<'a, 'b> {
+    f: &'a mut fmt::Formatter<'b>,
+    extra: &'a [RawProp],
+    /// How many known properties were written so far.
+    known: usize,
+}
+
+impl<'a, 'b> Props<'a, 'b> {
+    fn new(f: &'a mut fmt::Formatter<'b>, extra: &'a [RawProp]) -> Self {
+        Self { f, extra, known: 0 }
+    }
+
+    /// Write a known property line.
+    fn line(&mut self, line: fmt::Arguments<'_>) -> fmt::Result {
+        for raw in self.extra {
+            if raw.after == self.known {
+                writeln!(self.f, "{}", raw.line)?;
+            }
+        }
+        writeln!(self.f, "{line}")?;
+        self.known += 1;
+        Ok(())
+    }
+
+    /// Write unknown properties that go after all the known ones written.
+    fn finish(self) -> fmt::Result {
+        for raw in self.extra {
+            if raw.after >= self.known {
+                writeln!(self.f, "{}", raw.line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write a text, wrapping it into triple quotes if it spans multiple lines.
+fn write_text(f: &mut fmt::Formatter<'_>, text: &str, quoted: bool) -> fmt::Result {
+    if quoted || text.contains('\n') {
+        writeln!(f, r#"""""#)?;
+        writeln!(f, "{text}")?;
+        writeln!(f, r#"""""#)
+    } else {
+        writeln!(f, "{text}")
+    }
+}
+
+fn write_name(p: &mut Props, name: &Option<String>) -> fmt::Result {
+    if let Some(name) = name {
+        p.line(format_args!("NAME {name}"))?;
+    }
+    Ok(())
+}
+
+fn write_palette(f: &mut fmt::Formatter<'_>, pal: &Palette) -> fmt::Result {
+    let mut p = Props::new(f, &pal.extra);
+    write_name(&mut p, &pal.name)?;
+    for [r, g, b] in &pal.colors {
+        p.line(format_args!("{r},{g},{b}"))?;
+    }
+    p.finish()
+}
+
+fn write_room(f: &mut fmt::Formatter<'_>, room: &Room, commas: bool) -> fmt::Result {
+    let sep = if commas { "," } else { "" };
+    for row in &room.tiles {
+        writeln!(f, "{}", row.join(sep))?;
+    }
+    let mut p = Props::new(f, &room.extra);
+    write_name(&mut p, &room.name)?;
+    if !room.walls.is_empty() {
+        p.line(format_args!("WAL {}", room.walls.join(",")))?;
+    }
+    for item in &room.items {
+        p.line(format_args!("ITM {} {},{}", item.id, item.x, item.y))?;
+    }
+    for exit in &room.exits {
+        let Exit { x, y, room, .. } = exit;
+        let mut line = format!("EXT {x},{y} {room} {},{}", exit.to_x, exit.to_y);
+        if let Some(transition) = &exit.transition {
+            line.push_str(" FX ");
+            line.push_str(transition.as_str());
+        }
+        if let Some(dialog) = &exit.dialog {
+            line.push_str(" DLG ");
+            line.push_str(dialog);
+        }
+        for extra in &exit.extra {
+            line.push(' ');
+            line.push_str(extra);
+        }
+        p.line(format_args!("{line}"))?;
+    }
+    for end in &room.endings {
+        p.line(format_args!("END {} {},{}", end.id, end.x, end.y))?;
+    }
+    if let Some(pal) = &room.palette {
+        p.line(format_args!("PAL {pal}"))?;
+    }
+    if let Some(tune) = &room.tune {
+        p.line(format_args!("TUNE {tune}"))?;
+    }
+    p.finish()
+}
+
+fn write_frames(f: &mut fmt::Formatter<'_>, frames: &[Frame]) -> fmt::Result {
+    for (i, frame) in frames.iter().enumerate() {
+        if i != 0 {
+            writeln!(f, ">")?;
+        }
+        for row in frame {
+            for pixel in row {
+                f.write_str(if *pixel { "1" } else { "0" })?;
+            }
+            writeln!(f)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_color(p: &mut Props, color: Option<u8>) -> fmt::Result {
+    if let Some(color) = color {
+        p.line(format_args!("COL {color}"))?;
+    }
+    Ok(())
+}
+
+fn write_blip_id(p: &mut Props, blip: &Option<ID>) -> fmt::Result {
+    if let Some(blip) = blip {
+        p.line(format_args!("BLIP {blip}"))?;
+    }
+    Ok(())
+}
+
+fn write_tile(f: &mut fmt::Formatter<'_>, tile: &Tile) -> fmt::Result {
+    write_frames(f, &tile.frames)?;
+    let mut p = Props::new(f, &tile.extra);
+    write_name(&mut p, &tile.name)?;
+    if let Some(wall) = tile.wall {
+        p.line(format_args!("WAL {wall}"))?;
+    }
+    write_color(&mut p, tile.color)?;
+    p.finish()
+}
+
+fn write_sprite(f: &mut fmt::Formatter<'_>, sprite: &Sprite) -> fmt::Result {
+    write_frames(f, &sprite.frames)?;
+    let mut p = Props::new(f, &sprite.extra);
+    write_name(&mut p, &sprite.name)?;
+    if let Some(dialog) = &sprite.dialog {
+        p.line(format_args!("DLG {dialog}"))?;
+    }
+    if let Some((room, x, y)) = &sprite.pos {
+        p.line(format_args!("POS {room} {x},{y}"))?;
+    }
+    for (item, count) in &sprite.inventory {
+        p.line(format_args!("ITM {item} {count}"))?;
+    }
+    write_color(&mut p, sprite.color)?;
+    write_blip_id(&mut p, &sprite.blip)?;
+    p.finish()
+}
+
+fn write_item(f: &mut fmt::Formatter<'_>, item: &Item) -> fmt::Result {
+    write_frames(f, &item.frames)?;
+    let mut p = Props::new(f, &item.extra);
+    write_name(&mut p, &item.name)?;
+    if let Some(dialog) = &item.dialog {
+        p.line(format_args!("DLG {dialog}"))?;
+    }
+    write_color(&mut p, item.color)?;
+    write_blip_id(&mut p, &item.blip)?;
+    p.finish()
+}
+
+fn write_dialog(f: &mut fmt::Formatter<'_>, dialog: &Dialog) -> fmt::Result {
+    write_text(f, &dialog.text, dialog.quoted)?;
+    let mut p = Props::new(f, &dialog.extra);
+    write_name(&mut p, &dialog.name)?;
+    p.finish()
+}
+
+/// Write properties as "name value". Properties without a value are written as just the name.
+fn write_props(p: &mut Props, props: &[(String, String)]) -> fmt::Result {
+    for (name, val) in props {
+        if val.is_empty() {
+            p.line(format_args!("{name}"))?;
+        } else {
+            p.line(format_args!("{name} {val}"))?;
+        }
+    }
+    Ok(())
+}
+
+fn write_tune(f: &mut fmt::Formatter<'_>, tune: &Tune) -> fmt::Result {
+    for (i, bar) in tune.bars.iter().enumerate() {
+        if i != 0 {
+            writeln!(f, ">")?;
+        }
+        for voice in bar {
+            writeln!(f, "{}", voice.join(","))?;
+        }
+    }
+    let mut p = Props::new(f, &[]);
+    write_name(&mut p, &tune.name)?;
+    write_props(&mut p, &tune.props)
+}
+
+fn write_blip(f: &mut fmt::Formatter<'_>, blip: &Blip) -> fmt::Result {
+    writeln!(f, "{}", blip.notes.join(","))?;
+    let mut p = Props::new(f, &[]);
+    write_name(&mut p, &blip.name)?;
+    write_props(&mut p, &blip.props)
+}